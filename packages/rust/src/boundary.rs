@@ -0,0 +1,183 @@
+use crate::fluid::Grid;
+use crate::obstacle::Obstacles;
+use wasm_bindgen::prelude::*;
+
+/// How one edge of the domain behaves.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boundary {
+  /// Reflective wall: the velocity component normal to it is mirrored, the
+  /// tangential one slides freely.
+  #[default]
+  FreeSlip,
+  /// Wall that also stops the tangential velocity.
+  NoSlip,
+  /// Fluid leaving this edge re-enters through the opposite one. Always set on
+  /// both edges of an axis.
+  Periodic,
+  /// Open edge: everything has zero gradient across it and the pressure is
+  /// held at zero, so fluid can leave.
+  Outflow,
+  /// Fluid enters with the velocity configured for the edge.
+  Inflow,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+  Left,
+  Right,
+  /// The first row, drawn at the top of the canvas.
+  Top,
+  Bottom,
+}
+
+impl Side {
+  pub fn opposite(self) -> Side {
+    match self {
+      Side::Left => Side::Right,
+      Side::Right => Side::Left,
+      Side::Top => Side::Bottom,
+      Side::Bottom => Side::Top,
+    }
+  }
+}
+
+/// Which quantity a buffer passed to `set_bnd` holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+  Scalar,
+  VelocityX,
+  VelocityY,
+  Pressure,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Edge {
+  pub kind: Boundary,
+  /// Velocity imposed by an `Inflow` edge.
+  pub vx: f64,
+  pub vy: f64,
+}
+
+impl Edge {
+  /// Ghost cell value for `field` given the value just inside the edge;
+  /// `normal` is the velocity component that points across the edge.
+  fn ghost(&self, field: Field, normal: Field, inside: f64) -> f64 {
+    let velocity = field == Field::VelocityX || field == Field::VelocityY;
+    match self.kind {
+      Boundary::FreeSlip if field == normal => -inside,
+      Boundary::NoSlip if velocity => -inside,
+      Boundary::Outflow if field == Field::Pressure => -inside,
+      Boundary::Inflow if field == Field::VelocityX => 2f64 * self.vx - inside,
+      Boundary::Inflow if field == Field::VelocityY => 2f64 * self.vy - inside,
+      _ => inside,
+    }
+  }
+}
+
+/// The four edges of a domain.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Boundaries {
+  pub left: Edge,
+  pub right: Edge,
+  pub top: Edge,
+  pub bottom: Edge,
+}
+
+impl Boundaries {
+  pub fn uniform(kind: Boundary) -> Boundaries {
+    let edge = Edge {
+      kind,
+      ..Edge::default()
+    };
+    Boundaries {
+      left: edge,
+      right: edge,
+      top: edge,
+      bottom: edge,
+    }
+  }
+
+  pub fn get(&self, side: Side) -> Edge {
+    match side {
+      Side::Left => self.left,
+      Side::Right => self.right,
+      Side::Top => self.top,
+      Side::Bottom => self.bottom,
+    }
+  }
+
+  pub fn get_mut(&mut self, side: Side) -> &mut Edge {
+    match side {
+      Side::Left => &mut self.left,
+      Side::Right => &mut self.right,
+      Side::Top => &mut self.top,
+      Side::Bottom => &mut self.bottom,
+    }
+  }
+
+  /// Changes the kind of one edge, keeping periodic edges paired: making an
+  /// edge periodic also wraps the opposite one, and replacing a periodic edge
+  /// gives its former partner the same new kind.
+  pub fn set(&mut self, side: Side, kind: Boundary) {
+    let was_periodic = self.get(side).kind == Boundary::Periodic;
+    self.get_mut(side).kind = kind;
+    if kind == Boundary::Periodic || was_periodic {
+      self.get_mut(side.opposite()).kind = kind;
+    }
+  }
+
+  pub fn periodic_x(&self) -> bool {
+    self.left.kind == Boundary::Periodic
+  }
+
+  pub fn periodic_y(&self) -> bool {
+    self.top.kind == Boundary::Periodic
+  }
+}
+
+/// Fills the ghost border of `x` according to the edges of `g`, then the
+/// cells covered by obstacles.
+pub fn set_bnd(g: Grid, obs: &Obstacles, field: Field, x: &mut [f64]) {
+  let (w, h) = (g.width, g.height);
+  let e = g.boundaries;
+  let (periodic_x, periodic_y) = (e.periodic_x(), e.periodic_y());
+
+  if !periodic_y {
+    for i in 1..(w - 1) {
+      x[g.ix(i, 0)] = e.top.ghost(field, Field::VelocityY, x[g.ix(i, 1)]);
+      x[g.ix(i, h - 1)] = e.bottom.ghost(field, Field::VelocityY, x[g.ix(i, h - 2)]);
+    }
+  }
+
+  if !periodic_x {
+    for j in 1..(h - 1) {
+      x[g.ix(0, j)] = e.left.ghost(field, Field::VelocityX, x[g.ix(1, j)]);
+      x[g.ix(w - 1, j)] = e.right.ghost(field, Field::VelocityX, x[g.ix(w - 2, j)]);
+    }
+  }
+
+  // wrapped edges copy whole rows and columns, corners included
+  if periodic_x {
+    for j in 0..h {
+      x[g.ix(0, j)] = x[g.ix(w - 2, j)];
+      x[g.ix(w - 1, j)] = x[g.ix(1, j)];
+    }
+  }
+  if periodic_y {
+    for i in 0..w {
+      x[g.ix(i, 0)] = x[g.ix(i, h - 2)];
+      x[g.ix(i, h - 1)] = x[g.ix(i, 1)];
+    }
+  }
+
+  if !periodic_x && !periodic_y {
+    x[g.ix(0, 0)] = 0.5f64 * (x[g.ix(1, 0)] + x[g.ix(0, 1)]);
+    x[g.ix(0, h - 1)] = 0.5f64 * (x[g.ix(1, h - 1)] + x[g.ix(0, h - 2)]);
+    x[g.ix(w - 1, 0)] = 0.5f64 * (x[g.ix(w - 2, 0)] + x[g.ix(w - 1, 1)]);
+    x[g.ix(w - 1, h - 1)] = 0.5f64 * (x[g.ix(w - 2, h - 1)] + x[g.ix(w - 1, h - 2)]);
+  }
+
+  obs.set_bnd(g, field, x);
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+/// Why a `Fluid` call was rejected. Thrown as a string on the JS side.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FluidError {
+  /// A per-cell buffer did not have one value per grid cell.
+  BufferLength { expected: usize, found: usize },
+  /// The grid had no interior cell inside its one-cell border.
+  GridSize { width: i32, height: i32 },
+  /// A parameter was negative, zero where that makes no sense, or NaN.
+  InvalidParameter { name: &'static str, value: f64 },
+}
+
+impl fmt::Display for FluidError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      FluidError::BufferLength { expected, found } => {
+        write!(f, "expected {} values, one per cell, but got {}", expected, found)
+      }
+      FluidError::GridSize { width, height } => {
+        write!(f, "a {}x{} grid is too small, it needs at least 3x3 cells", width, height)
+      }
+      FluidError::InvalidParameter { name, value } => write!(f, "{} cannot be {}", name, value),
+    }
+  }
+}
+
+impl std::error::Error for FluidError {}
+
+impl From<FluidError> for JsValue {
+  fn from(error: FluidError) -> JsValue {
+    JsValue::from_str(&error.to_string())
+  }
+}
@@ -1,217 +1,1083 @@
-const N:i32 = 150;
-const iter:i32 = 1;
+use crate::advection::{self, AdvectConfig, Advection, Backtrace, Interpolation};
+use crate::boundary::{set_bnd, Boundaries, Boundary, Field, Side};
+use crate::error::FluidError;
+use crate::force::{self, Force};
+use crate::multigrid;
+use crate::obstacle::{Body, Obstacles, Shape};
+use crate::pcg;
+use crate::scalar::Scalars;
+use crate::spectral;
+use crate::solver::{PressureSolver, SolveStats, SolverConfig};
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+
 // const SCALE:i32 = 4;
 
-fn constrain(x:i32,min:i32,max:i32) ->i32{
-    if x < min {
-        return min;
-    }else if x>max{
-        return max;
+/// Steps of drag and lift kept per body.
+const FORCE_HISTORY: usize = 4096;
+
+/// Most sub-steps one adaptive `Fluid::step` may take.
+const MAX_SUBSTEPS: u32 = 64;
+
+/// Exponential decay of `x` by `rate` per second over a step of `dt`.
+fn fade(x: &mut [f64], rate: f64, dt: f64) {
+  if rate > 0f64 {
+    let factor = (-rate * dt).exp();
+    x.iter_mut().for_each(|v| *v *= factor);
+  }
+}
+
+fn constrain(x: i32, min: i32, max: i32) -> i32 {
+  if x < min {
+    return min;
+  } else if x > max {
+    return max;
+  }
+  x
+}
+
+/// Dimensions of the simulation grid, including the one-cell border used for
+/// boundary conditions, and how that border behaves. Cells are square, so a
+/// widescreen grid simply has more columns than rows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+  pub width: i32,
+  pub height: i32,
+  pub boundaries: Boundaries,
+}
+
+impl Grid {
+  pub fn new(width: i32, height: i32) -> Grid {
+    Grid {
+      width,
+      height,
+      boundaries: Boundaries::default(),
+    }
+  }
+
+  /// Like `new`, but rejects grids without at least one interior cell
+  /// inside the border.
+  pub fn checked(width: i32, height: i32) -> Result<Grid, FluidError> {
+    if width < 3 || height < 3 {
+      return Err(FluidError::GridSize { width, height });
     }
-    return x;
+    Ok(Grid::new(width, height))
+  }
+
+  pub fn cells(&self) -> usize {
+    (self.width * self.height) as usize
+  }
+
+  pub fn ix(&self, x_: i32, y_: i32) -> usize {
+    let x = constrain(x_, 0, self.width - 1);
+    let y = constrain(y_, 0, self.height - 1);
+    (x + (y * self.width)) as usize
+  }
+
+  /// Number of grid cells per unit of simulation length, shared by both axes.
+  pub(crate) fn scale(&self) -> f64 {
+    (self.width.max(self.height) - 2) as f64
+  }
 }
 
-fn IX( x_:i32, y_:i32) -> usize {
-  let x = constrain(x_, 0, N-1);
-  let y = constrain(y_, 0, N-1);
-  return (x + (y * N)) as usize;
+/// Accepts `value` for parameter `name` only if it is a finite number that is
+/// positive, or zero when `allow_zero` is set.
+pub(crate) fn check(name: &'static str, value: f64, allow_zero: bool) -> Result<f64, FluidError> {
+  let valid = value.is_finite() && (value > 0f64 || (allow_zero && value == 0f64));
+  if valid {
+    Ok(value)
+  } else {
+    Err(FluidError::InvalidParameter { name, value })
+  }
 }
 
+/// A single simulation. JS can create as many of these as it needs and
+/// release each one with `free()` once it is no longer rendered.
+#[wasm_bindgen]
 pub struct Fluid {
-  size:i32,
-  dt:f64, //time step
-  diff:f64, //diffusion amount
-  visc:f64, //thickness of fluid
+  grid: Grid,
+  dt: f64,   //time step
+  diff: f64, //diffusion amount
+  visc: f64, //thickness of fluid
+  vorticity: f64, //strength of vorticity confinement
+  cfl: f64,       //cells a step may move the fluid, 0 for a fixed dt
+  substeps: u32,  //sub-steps taken by the last step
+  backlog: f64,   //time `advance` has not simulated yet
+  advanced: u32,  //steps the last `advance` call took
+  max_steps: u32, //steps one `advance` may take
+  buoyancy: f64,  //upward push per degree of temperature
+  weight: f64,    //downward pull per unit of density
+
+  density_decay: f64, //fraction lost per second, as an exponential rate
+  temperature_decay: f64,
+  velocity_decay: f64,
+
+  force: (f64, f64),          //acceleration everywhere, e.g. gravity or wind
+  force_map: Option<(Vec<f64>, Vec<f64>)>, //extra acceleration per cell
 
-  s:Vec<f64>, //previous density
-  pub density:Vec<f64>,
+  s: Vec<f64>, //previous density
+  density: Vec<f64>,
+  temperature: Vec<f64>, //relative to the surrounding air
+  scalars: Scalars,      //named dyes and tracers
 
-  Vx:Vec<f64>,
-  Vy:Vec<f64>,
+  vx: Vec<f64>,
+  vy: Vec<f64>,
 
-  Vx0:Vec<f64>,
-  Vy0:Vec<f64>,
+  vx0: Vec<f64>,
+  vy0: Vec<f64>,
 
+  p: Vec<f64>,   //pressure from the last projection, times p_dt
+  p_dt: f64,     //sub-step dt the last projection used
+  div: Vec<f64>, //divergence before the last projection
+
+  obstacles: Obstacles,
+  forces: Vec<VecDeque<Force>>, //per body, oldest first
+
+  solver: SolverConfig,
+  pressure_solver: PressureSolver,
+  advect: AdvectConfig,
+  stats: SolveStats,
 }
-impl Fluid{
-  pub fn create(dt:f64, diffusion:f64,viscosity:f64) -> Fluid {
-    return Fluid{
-      size:N,
-      dt : dt,
-      diff : diffusion,
-      visc : viscosity,
-      s : vec![0f64;(N*N) as usize],
-      density : vec![0f64;(N*N) as usize],
-      Vx : vec![0f64;(N*N) as usize],
-      Vy : vec![0f64;(N*N) as usize],
-      Vx0 : vec![0f64;(N*N) as usize],
-      Vy0 : vec![0f64;(N*N) as usize],
-    }
+#[wasm_bindgen]
+impl Fluid {
+  #[wasm_bindgen(constructor)]
+  pub fn new(width: i32, height: i32, dt: f64, diffusion: f64, viscosity: f64) -> Result<Fluid, FluidError> {
+    let grid = Grid::checked(width, height)?;
+    let cells = grid.cells();
+    let dt = check("dt", dt, false)?;
+    Ok(Fluid {
+      grid,
+      dt,
+      diff: check("diffusion", diffusion, true)?,
+      visc: check("viscosity", viscosity, true)?,
+      vorticity: 0f64,
+      cfl: 0f64,
+      substeps: 1,
+      backlog: 0f64,
+      advanced: 0,
+      max_steps: 4,
+      buoyancy: 1f64,
+      weight: 0f64,
+      density_decay: 0f64,
+      temperature_decay: 0f64,
+      velocity_decay: 0f64,
+      force: (0f64, 0f64),
+      force_map: None,
+      s: vec![0f64; cells],
+      density: vec![0f64; cells],
+      temperature: vec![0f64; cells],
+      scalars: Scalars::default(),
+      vx: vec![0f64; cells],
+      vy: vec![0f64; cells],
+      vx0: vec![0f64; cells],
+      vy0: vec![0f64; cells],
+      p: vec![0f64; cells],
+      p_dt: dt,
+      div: vec![0f64; cells],
+      obstacles: Obstacles::new(grid),
+      forces: Vec::new(),
+      solver: SolverConfig::default(),
+      pressure_solver: PressureSolver::default(),
+      advect: AdvectConfig::default(),
+      stats: SolveStats::default(),
+    })
   }
 
+  /// Advances the simulation by `dt`, in several sub-steps when a CFL
+  /// number is set and the fluid moves too fast for a single one.
   pub fn step(&mut self) {
-    Fluid::diffuse(1, &mut self.Vx0, &mut self.Vx, self.visc, self.dt);
-    Fluid::diffuse(2, &mut self.Vy0, &mut self.Vy, self.visc, self.dt);
-    
-    Fluid::project(&mut self.Vx0, &mut self.Vy0, &mut self.Vx, &mut self.Vy);
-
-    let Vx0 = self.Vx0.clone(); 
-    let Vy0 = self.Vy0.clone();
-    Fluid::advect(1, &mut self.Vx, &mut self.Vx0, Vx0, Vy0, self.dt);
-    let Vx0 = self.Vx0.clone(); 
-    let Vy0 = self.Vy0.clone();
-    Fluid::advect(2, &mut self.Vy, &mut self.Vy0, Vx0, Vy0, self.dt);
-
-    Fluid::project(&mut self.Vx, &mut self.Vy, &mut self.Vx0, &mut self.Vy0);
-    
-    Fluid::diffuse(0, &mut self.s, &mut self.density, self.diff, self.dt);
-    let Vx = self.Vx.clone(); 
-    let Vy = self.Vy.clone();
-    Fluid::advect(0, &mut self.density, &mut self.s, Vx, Vy, self.dt);
- 
+    let g = self.grid;
+    self.substeps = 1;
+    if self.cfl > 0f64 {
+      let fastest = self.vx.iter().chain(&self.vy).fold(0f64, |m, v| m.max(v.abs()));
+      let cells = fastest * self.dt * g.scale();
+      self.substeps = ((cells / self.cfl).ceil() as u32).clamp(1, MAX_SUBSTEPS);
+    }
+    self.obstacles.aim(g, self.dt);
+    let dt = self.dt / self.substeps as f64;
+    let mut stats = SolveStats::default();
+    for _ in 0..self.substeps {
+      stats.accumulate(self.substep(dt));
+    }
+    self.stats = stats;
+    self.p_dt = dt;
+
+    let obs = &self.obstacles;
+    for id in obs.body_ids() {
+      let history = &mut self.forces[id as usize];
+      if history.len() == FORCE_HISTORY {
+        history.pop_front();
+      }
+      history.push_back(force::measure(g, obs, id, &self.p, &self.vx, &self.vy, self.visc, dt));
+    }
+  }
+
+  /// Simulates `elapsed_seconds` of real time in fixed steps of `dt`, so
+  /// playback speed does not depend on the frame rate. Time left over is
+  /// carried to the next call; beyond `max_steps` steps it is dropped so a
+  /// slow frame cannot snowball. Returns how far the leftover time is into
+  /// the next step, from 0 to 1, for interpolating between frames.
+  pub fn advance(&mut self, elapsed_seconds: f64) -> f64 {
+    if elapsed_seconds.is_finite() && elapsed_seconds > 0f64 {
+      self.backlog += elapsed_seconds;
+    }
+    let mut steps = 0;
+    while self.backlog >= self.dt && steps < self.max_steps {
+      self.step();
+      self.backlog -= self.dt;
+      steps += 1;
+    }
+    if self.backlog >= self.dt {
+      self.backlog %= self.dt;
+    }
+    self.advanced = steps;
+    self.backlog / self.dt
+  }
+
+  /// Steps the last `advance` call took, to inject sources at a rate that
+  /// does not depend on the frame rate.
+  #[wasm_bindgen(getter)]
+  pub fn steps_taken(&self) -> u32 {
+    self.advanced
   }
 
-  pub fn addDensity(&mut self, x:i32,y:i32, amount:f64) {
-    let index = IX(x, y);
+  /// Most steps one `advance` call takes, 4 by default.
+  #[wasm_bindgen(getter)]
+  pub fn max_steps(&self) -> u32 {
+    self.max_steps
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_max_steps(&mut self, max_steps: u32) {
+    self.max_steps = max_steps;
+  }
+
+  pub fn add_density(&mut self, x: i32, y: i32, amount: f64) {
+    let index = self.grid.ix(x, y);
     self.density[index] += amount;
   }
 
+  /// Heats cell `(x, y)`; negative amounts cool it. Warm fluid rises.
+  pub fn add_temperature(&mut self, x: i32, y: i32, amount: f64) {
+    let index = self.grid.ix(x, y);
+    self.temperature[index] += amount;
+  }
+
+  pub fn add_velocity(&mut self, x: i32, y: i32, amount_x: f64, amount_y: f64) {
+    let index = self.grid.ix(x, y);
+    self.vx[index] += amount_x;
+    self.vy[index] += amount_y;
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn width(&self) -> i32 {
+    self.grid.width
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn height(&self) -> i32 {
+    self.grid.height
+  }
 
-  pub fn addVelocity(&mut self,x:i32,  y:i32, amountX:f64,amountY:f64) {
-    let index = IX(x, y);
-    self.Vx[index] += amountX;
-    self.Vy[index] += amountY;
+  pub fn edge(&self, side: Side) -> Boundary {
+    self.grid.boundaries.get(side).kind
   }
 
+  /// Sets how one edge behaves. Periodic edges come in pairs, see
+  /// `Boundaries::set`.
+  pub fn set_edge(&mut self, side: Side, kind: Boundary) {
+    self.grid.boundaries.set(side, kind);
+  }
 
+  /// Makes `side` an inflow edge that feeds in fluid at velocity `(vx, vy)`.
+  pub fn set_inflow(&mut self, side: Side, vx: f64, vy: f64) {
+    self.grid.boundaries.set(side, Boundary::Inflow);
+    let edge = self.grid.boundaries.get_mut(side);
+    edge.vx = vx;
+    edge.vy = vy;
+  }
 
+  /// Gives all four edges the same behaviour.
+  pub fn set_boundary(&mut self, kind: Boundary) {
+    self.grid.boundaries = Boundaries::uniform(kind);
+  }
 
-  fn diffuse(b: i32, x: &mut Vec<f64>, x0: &mut Vec<f64>, diff: f64, dt: f64) {
-    let a: f64 = dt * diff * ((N - 2) * (N - 2) ) as f64;
-    Fluid::lin_solve(b, x, x0, a, 1f64 + 6f64 * a);
+  #[wasm_bindgen(getter)]
+  pub fn density(&self) -> Vec<f64> {
+    self.density.clone()
   }
 
-  fn lin_solve(b: i32, x: &mut Vec<f64>, x0: &mut Vec<f64>, a: f64, c: f64) {
-    let cRecip = 1.0 / c;
-    for k in 0..iter {
-      for j in 1..(N - 1) {
-        for i in 1..(N - 1) {
-          x[IX(i, j)] =
-            (x0[IX(i, j)]
-            + a*(    x[IX(i+1, j)]
-            + x[IX(i-1, j)]
-            + x[IX(i, j+1)]
-            + x[IX(i, j-1)]
-            )) * cRecip;
-        }
+  /// Number of values in every per-cell buffer, ghost border included.
+  #[wasm_bindgen(getter)]
+  pub fn cells(&self) -> usize {
+    self.grid.cells()
+  }
+
+  /// Address of the density buffer in wasm memory, so JS can read it in
+  /// place with `new Float64Array(wasm_memory().buffer, ptr, fluid.cells)`
+  /// instead of copying it out every frame. The buffers never move for the
+  /// life of the fluid, but the view must be rebuilt whenever the wasm
+  /// memory grows, which detaches `buffer`.
+  pub fn density_ptr(&self) -> *const f64 {
+    self.density.as_ptr()
+  }
+
+  /// Like `density_ptr`, for the horizontal velocity.
+  pub fn velocity_x_ptr(&self) -> *const f64 {
+    self.vx.as_ptr()
+  }
+
+  /// Like `density_ptr`, for the vertical velocity.
+  pub fn velocity_y_ptr(&self) -> *const f64 {
+    self.vy.as_ptr()
+  }
+
+  /// Like `density_ptr`, for scalar `name`; null if there is no such field.
+  /// Adding or removing scalar fields may move the others.
+  pub fn scalar_ptr(&self, name: &str) -> *const f64 {
+    self.scalars.get(name).map_or(std::ptr::null(), |f| f.values.as_ptr())
+  }
+
+  /// Horizontal velocity of every cell, laid out like `density`.
+  #[wasm_bindgen(getter)]
+  pub fn velocity_x(&self) -> Vec<f64> {
+    self.vx.clone()
+  }
+
+  /// Vertical velocity of every cell, positive down the screen.
+  #[wasm_bindgen(getter)]
+  pub fn velocity_y(&self) -> Vec<f64> {
+    self.vy.clone()
+  }
+
+  /// Length of the velocity of every cell.
+  #[wasm_bindgen(getter)]
+  pub fn speed(&self) -> Vec<f64> {
+    self.vx.iter().zip(&self.vy).map(|(x, y)| x.hypot(*y)).collect()
+  }
+
+  /// Direction of the velocity of every cell, in radians from the +x axis
+  /// towards +y (clockwise on screen), as `Math.atan2(vy, vx)` would give.
+  #[wasm_bindgen(getter)]
+  pub fn direction(&self) -> Vec<f64> {
+    self.vx.iter().zip(&self.vy).map(|(x, y)| y.atan2(*x)).collect()
+  }
+
+  /// Pressure solved for by the last projection, in the units `force` uses.
+  /// Only differences matter; with closed edges it is relative to its mean.
+  #[wasm_bindgen(getter)]
+  pub fn pressure(&self) -> Vec<f64> {
+    self.p.iter().map(|p| p / self.p_dt).collect()
+  }
+
+  /// Divergence of the current velocity, per second; near zero in the fluid
+  /// after a step, up to the solver tolerance.
+  #[wasm_bindgen(getter)]
+  pub fn divergence(&self) -> Vec<f64> {
+    let g = self.grid;
+    let mut div = vec![0f64; g.cells()];
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        div[g.ix(i, j)] = 0.5f64
+          * ((self.vx[g.ix(i + 1, j)] - self.vx[g.ix(i - 1, j)]) + (self.vy[g.ix(i, j + 1)] - self.vy[g.ix(i, j - 1)]))
+          * g.scale();
       }
-      //Fluid::set_bnd(b, x);
     }
+    div
   }
 
-  fn project(velocX: &mut Vec<f64>, velocY: &mut Vec<f64>, p: &mut Vec<f64>, div: &mut Vec<f64>) {
-    for j in 1..(N - 1) {
-      for i in 1..(N - 1) {
-        div[IX(i, j)] = -0.5f64*(
-          (velocX[IX(i+1, j)]-velocX[IX(i-1, j)])/(N as f64) + 
-          (velocY[IX(i, j+1)]-velocY[IX(i, j-1)])/(N as f64)
-          );
-        p[IX(i, j)] = 0f64;
-      }
+  #[wasm_bindgen(getter)]
+  pub fn temperature(&self) -> Vec<f64> {
+    self.temperature.clone()
+  }
+
+  /// Adds a passive scalar field called `name` with its own diffusion rate,
+  /// e.g. one channel of coloured dye. Adding a name twice only updates the
+  /// diffusion rate, which must be finite and not negative.
+  pub fn add_scalar_field(&mut self, name: &str, diffusion: f64) -> Result<(), FluidError> {
+    let diffusion = check("diffusion", diffusion, true)?;
+    self.scalars.insert(name, diffusion, self.grid.cells());
+    Ok(())
+  }
+
+  pub fn remove_scalar_field(&mut self, name: &str) {
+    self.scalars.remove(name);
+  }
+
+  /// Names of the scalar fields, in the order they were added.
+  #[wasm_bindgen(getter)]
+  pub fn scalar_names(&self) -> Vec<String> {
+    self.scalars.names()
+  }
+
+  /// Adds `amount` of scalar `name` to cell `(x, y)`; unknown names are
+  /// ignored.
+  pub fn add_scalar(&mut self, name: &str, x: i32, y: i32, amount: f64) {
+    let index = self.grid.ix(x, y);
+    if let Some(field) = self.scalars.get_mut(name) {
+      field.values[index] += amount;
     }
-    Fluid::set_bnd(0, div); 
-    Fluid::set_bnd(0, p);
-    Fluid::lin_solve(0, p, div, 1f64, 6f64);
+  }
+
+  /// Makes scalar `name` fade by `rate` per second, see `density_decay`.
+  pub fn set_scalar_decay(&mut self, name: &str, rate: f64) {
+    if let Some(field) = self.scalars.get_mut(name) {
+      field.decay = rate;
+    }
+  }
 
-    for j in 1..(N - 1) {
-      for i in 1..(N - 1) {
-        velocX[IX(i, j)] -= 0.5f64 * (   p[IX(i+1, j)]
-          - p[IX(i-1, j)]) * (N as f64);
-        velocY[IX(i, j)] -= 0.5f64 * (   p[IX(i, j+1)]
-          - p[IX(i, j-1)]) * (N as f64);
+  /// Values of scalar `name`, or `undefined` if there is no such field.
+  pub fn scalar(&self, name: &str) -> Option<Vec<f64>> {
+    self.scalars.get(name).map(|f| f.values.clone())
+  }
+
+  /// Accelerates every fluid cell by `(fx, fy)` each step, e.g. gravity or a
+  /// steady wind.
+  pub fn set_uniform_force(&mut self, fx: f64, fy: f64) {
+    self.force = (fx, fy);
+  }
+
+  /// Adds a per-cell acceleration on top of the uniform force, one `fx` and
+  /// one `fy` per grid cell in the same order as `density`.
+  pub fn set_force_map(&mut self, fx: Vec<f64>, fy: Vec<f64>) -> Result<(), FluidError> {
+    let cells = self.grid.cells();
+    for found in [fx.len(), fy.len()] {
+      if found != cells {
+        return Err(FluidError::BufferLength { expected: cells, found });
       }
     }
-    Fluid::set_bnd(1, velocX);
-    Fluid::set_bnd(2, velocY);
+    self.force_map = Some((fx, fy));
+    Ok(())
+  }
+
+  pub fn clear_force_map(&mut self) {
+    self.force_map = None;
+  }
+
+  /// Exponential decay rate of the density, per second: each step scales it
+  /// by `exp(-rate * dt)`, so a steady source levels off instead of
+  /// saturating. 0 keeps it forever.
+  #[wasm_bindgen(getter)]
+  pub fn density_decay(&self) -> f64 {
+    self.density_decay
   }
 
+  #[wasm_bindgen(setter)]
+  pub fn set_density_decay(&mut self, rate: f64) {
+    self.density_decay = rate;
+  }
 
-  fn advect(b: i32, d: &mut Vec<f64>, d0: &mut Vec<f64>, velocX: Vec<f64>, velocY: Vec<f64>, dt: f64)
-  {
-    // float i0, i1, j0, j1;
+  /// How fast heat is lost to the surroundings, see `density_decay`.
+  #[wasm_bindgen(getter)]
+  pub fn temperature_decay(&self) -> f64 {
+    self.temperature_decay
+  }
 
-    let dtx = dt * ((N - 2) as f64);
-    let dty = dt * ((N - 2) as f64);
+  #[wasm_bindgen(setter)]
+  pub fn set_temperature_decay(&mut self, rate: f64) {
+    self.temperature_decay = rate;
+  }
 
-    // float s0, s1, t0, t1;
-    // float tmp1, tmp2, x, y;
+  /// Damping of the velocity, see `density_decay`.
+  #[wasm_bindgen(getter)]
+  pub fn velocity_decay(&self) -> f64 {
+    self.velocity_decay
+  }
 
-    let Nfloat = N as f64;
-    
-    // float ifloat, jfloat;
-    // int i, j;
+  #[wasm_bindgen(setter)]
+  pub fn set_velocity_decay(&mut self, rate: f64) {
+    self.velocity_decay = rate;
+  }
 
-    for j in 1..(N - 1) { 
-      for i in 1..(N - 1) {
-        let jfloat = j as f64;
-        let ifloat = i as f64;
-        let tmp1 = dtx * velocX[IX(i, j)];
-        let tmp2 = dty * velocY[IX(i, j)];
-        let mut x = ifloat - tmp1; 
-        let mut y = jfloat - tmp2;
+  /// Upward acceleration per degree of temperature.
+  #[wasm_bindgen(getter)]
+  pub fn buoyancy(&self) -> f64 {
+    self.buoyancy
+  }
 
-        if x < 0.5f64 {
-          x = 0.5f64; 
-        }
-        if x > Nfloat + 0.5f64 {
-          x = Nfloat + 0.5f64; 
-        }
-        let i0 = x.floor(); 
-        let i1 = i0 + 1.0f64;
-        if y < 0.5f64 {
-          y = 0.5f64; 
-        }
-        if y > Nfloat + 0.5f64 {
-          y = Nfloat + 0.5f64; 
+  #[wasm_bindgen(setter)]
+  pub fn set_buoyancy(&mut self, buoyancy: f64) {
+    self.buoyancy = buoyancy;
+  }
+
+  /// Downward acceleration per unit of density, so thick smoke sinks unless
+  /// it is hot enough.
+  #[wasm_bindgen(getter)]
+  pub fn smoke_weight(&self) -> f64 {
+    self.weight
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_smoke_weight(&mut self, weight: f64) {
+    self.weight = weight;
+  }
+
+  /// Makes cell `(x, y)` solid, or fluid again. Whatever was in a cell that
+  /// turns solid is removed.
+  pub fn set_obstacle(&mut self, x: i32, y: i32, solid: bool) {
+    if let Some(k) = self.obstacles.set(self.grid, x, y, solid) {
+      self.clear_scalars(k);
+      self.vx[k] = 0f64;
+      self.vy[k] = 0f64;
+    }
+  }
+
+  /// Sets every cell from `(x0, y0)` to `(x1, y1)` inclusive.
+  pub fn set_obstacle_rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, solid: bool) {
+    for y in y0.min(y1)..=y0.max(y1) {
+      for x in x0.min(x1)..=x0.max(x1) {
+        self.set_obstacle(x, y, solid);
+      }
+    }
+  }
+
+  /// Sets every cell whose centre lies within `radius` of `(cx, cy)`.
+  pub fn set_obstacle_circle(&mut self, cx: f64, cy: f64, radius: f64, solid: bool) {
+    let r = radius.ceil() as i32;
+    let (ci, cj) = (cx.round() as i32, cy.round() as i32);
+    for y in (cj - r)..=(cj + r) {
+      for x in (ci - r)..=(ci + r) {
+        let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+        if dx * dx + dy * dy <= radius * radius {
+          self.set_obstacle(x, y, solid);
         }
-        let j0 = y.floor();
-        let j1 = j0 + 1.0f64; 
+      }
+    }
+  }
+
+  /// Removes every painted obstacle cell and every moving obstacle.
+  pub fn clear_obstacles(&mut self) {
+    self.obstacles.clear(self.grid);
+    self.forces.clear();
+  }
+
+  /// Adds a moving rectangular obstacle centred on `(x, y)` and rotated by
+  /// `angle` radians. Returns its id.
+  pub fn add_rect_body(&mut self, x: f64, y: f64, width: f64, height: f64, angle: f64) -> u32 {
+    self.add_body(Body::new(Shape::Rect { width, height }, x, y, angle))
+  }
+
+  /// Adds a moving circular obstacle centred on `(x, y)`. Returns its id.
+  pub fn add_circle_body(&mut self, x: f64, y: f64, radius: f64) -> u32 {
+    self.add_body(Body::new(Shape::Circle { radius }, x, y, 0f64))
+  }
+
+  pub fn remove_body(&mut self, id: u32) {
+    self.obstacles.remove_body(self.grid, id);
+    if let Some(history) = self.forces.get_mut(id as usize) {
+      history.clear();
+    }
+  }
+
+  /// Sets how fast a moving obstacle travels and spins (radians per second,
+  /// clockwise on screen).
+  pub fn set_body_velocity(&mut self, id: u32, vx: f64, vy: f64, angular_velocity: f64) {
+    if let Some(body) = self.obstacles.body_mut(id) {
+      body.vx = vx;
+      body.vy = vy;
+      body.angular_velocity = angular_velocity;
+      body.target = None;
+    }
+  }
+
+  /// Steers a moving obstacle to `(x, y)`, e.g. to follow the mouse. Every
+  /// step re-aims it, so it arrives by the end of the next step and then
+  /// stays put, however many steps `advance` takes. `set_body_velocity`
+  /// cancels it.
+  pub fn move_body_to(&mut self, id: u32, x: f64, y: f64) {
+    if let Some(body) = self.obstacles.body_mut(id) {
+      body.target = Some((x, y));
+    }
+  }
+
+  /// Drag and lift on body `id` during the last step.
+  pub fn force(&self, id: u32) -> Force {
+    self.forces.get(id as usize).and_then(|h| h.back().copied()).unwrap_or_default()
+  }
+
+  /// Drag on body `id` over the last steps, oldest first.
+  pub fn drag_history(&self, id: u32) -> Vec<f64> {
+    self.forces.get(id as usize).map_or_else(Vec::new, |h| h.iter().map(|f| f.drag).collect())
+  }
+
+  /// Lift on body `id` over the last steps, oldest first.
+  pub fn lift_history(&self, id: u32) -> Vec<f64> {
+    self.forces.get(id as usize).map_or_else(Vec::new, |h| h.iter().map(|f| f.lift).collect())
+  }
+
+  pub fn clear_force_history(&mut self) {
+    self.forces.iter_mut().for_each(VecDeque::clear);
+  }
+
+  pub fn is_obstacle(&self, x: i32, y: i32) -> bool {
+    self.obstacles.is_solid(self.grid.ix(x, y))
+  }
+
+  /// Obstacle mask, one byte per cell (1 for solid), for drawing.
+  #[wasm_bindgen(getter)]
+  pub fn obstacles(&self) -> Vec<u8> {
+    self.obstacles.mask().iter().map(|&s| s as u8).collect()
+  }
+
+  /// Length of one step, in seconds.
+  #[wasm_bindgen(getter)]
+  pub fn dt(&self) -> f64 {
+    self.dt
+  }
+
+  pub fn set_dt(&mut self, dt: f64) -> Result<(), FluidError> {
+    self.dt = check("dt", dt, false)?;
+    Ok(())
+  }
+
+  /// How fast density and temperature spread out. Named scalar fields have
+  /// their own rates, set with `add_scalar_field`.
+  #[wasm_bindgen(getter)]
+  pub fn diffusion(&self) -> f64 {
+    self.diff
+  }
+
+  pub fn set_diffusion(&mut self, diffusion: f64) -> Result<(), FluidError> {
+    self.diff = check("diffusion", diffusion, true)?;
+    Ok(())
+  }
+
+  /// How thick the fluid is.
+  #[wasm_bindgen(getter)]
+  pub fn viscosity(&self) -> f64 {
+    self.visc
+  }
+
+  pub fn set_viscosity(&mut self, viscosity: f64) -> Result<(), FluidError> {
+    self.visc = check("viscosity", viscosity, true)?;
+    Ok(())
+  }
+
+  /// Upper bound on the sweeps, cycles or iterations of each linear solve;
+  /// shorthand for `solver.max_iterations`.
+  #[wasm_bindgen(getter)]
+  pub fn iterations(&self) -> u32 {
+    self.solver.max_iterations
+  }
+
+  /// Takes a plain JS number so a negative or fractional slider value is
+  /// rejected instead of wrapping around.
+  pub fn set_iterations(&mut self, iterations: f64) -> Result<(), FluidError> {
+    let iterations = check("iterations", iterations, false)?;
+    if iterations.fract() != 0f64 || iterations > u32::MAX as f64 {
+      return Err(FluidError::InvalidParameter {
+        name: "iterations",
+        value: iterations,
+      });
+    }
+    self.solver.max_iterations = iterations as u32;
+    Ok(())
+  }
+
+  /// Adaptive time stepping: each step is split into as many sub-steps as
+  /// needed for the fastest fluid to cross at most `cfl` cells per sub-step,
+  /// up to 64. 0, the default, always takes a single step of `dt`.
+  #[wasm_bindgen(getter)]
+  pub fn cfl(&self) -> f64 {
+    self.cfl
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_cfl(&mut self, cfl: f64) {
+    self.cfl = cfl;
+  }
+
+  /// Sub-steps taken by the last step.
+  #[wasm_bindgen(getter)]
+  pub fn substeps(&self) -> u32 {
+    self.substeps
+  }
+
+  /// Strength of the vorticity confinement force that keeps small swirls
+  /// from being smoothed away; 0 turns it off.
+  #[wasm_bindgen(getter)]
+  pub fn vorticity(&self) -> f64 {
+    self.vorticity
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_vorticity(&mut self, strength: f64) {
+    self.vorticity = strength;
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn solver(&self) -> SolverConfig {
+    self.solver
+  }
+
+  /// Needs at least one iteration and a finite, non-negative tolerance.
+  pub fn set_solver(&mut self, solver: SolverConfig) -> Result<(), FluidError> {
+    self.solver = solver.checked()?;
+    Ok(())
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn pressure_solver(&self) -> PressureSolver {
+    self.pressure_solver
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_pressure_solver(&mut self, pressure_solver: PressureSolver) {
+    self.pressure_solver = pressure_solver;
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn advection(&self) -> Advection {
+    self.advect.scheme
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_advection(&mut self, advection: Advection) {
+    self.advect.scheme = advection;
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn backtrace(&self) -> Backtrace {
+    self.advect.backtrace
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_backtrace(&mut self, backtrace: Backtrace) {
+    self.advect.backtrace = backtrace;
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn interpolation(&self) -> Interpolation {
+    self.advect.interpolation
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+    self.advect.interpolation = interpolation;
+  }
+
+  /// Density at `(x, y)`, in cells, between cell centres, reconstructed
+  /// with this fluid's interpolation.
+  pub fn sample_density(&self, x: f64, y: f64) -> f64 {
+    advection::sample(self.grid, &self.density, x, y, self.advect.interpolation)
+  }
+
+  /// Like `sample_density` for scalar `name`; `undefined` if there is no such
+  /// field.
+  pub fn sample_scalar(&self, name: &str, x: f64, y: f64) -> Option<f64> {
+    let field = self.scalars.get(name)?;
+    Some(advection::sample(self.grid, &field.values, x, y, self.advect.interpolation))
+  }
+
+  /// Iterations used and residual reached by the pressure solves of the last
+  /// step.
+  #[wasm_bindgen(getter)]
+  pub fn solve_stats(&self) -> SolveStats {
+    self.stats
+  }
+}
+
+impl Fluid {
+  pub fn grid(&self) -> Grid {
+    self.grid
+  }
+
+  pub fn velocity(&self) -> (&[f64], &[f64]) {
+    (&self.vx, &self.vy)
+  }
+
+  /// One solver step of `dt`: forces, velocity diffusion, projection and
+  /// advection, then the scalars.
+  fn substep(&mut self, dt: f64) -> SolveStats {
+    let g = self.grid;
+    for k in self.obstacles.update(g, dt) {
+      self.clear_scalars(k);
+    }
+    let obs = &self.obstacles;
+    let cfg = self.solver;
+    let ps = self.pressure_solver;
+    let advect = self.advect;
+    let (visc, diff) = (self.visc * dt, self.diff * dt);
+    let mut stats = SolveStats::default();
+    if self.vorticity > 0f64 {
+      Fluid::confine(g, obs, &mut self.vx, &mut self.vy, &mut self.vx0, self.vorticity, dt);
+    }
+    Fluid::apply_buoyancy(g, obs, &mut self.vy, &self.temperature, &self.density, self.buoyancy, self.weight, dt);
+    Fluid::apply_forces(g, obs, &mut self.vx, &mut self.vy, self.force, self.force_map.as_ref(), dt);
+    Fluid::diffuse(g, obs, Field::VelocityX, &mut self.vx0, &self.vx, visc, cfg);
+    Fluid::diffuse(g, obs, Field::VelocityY, &mut self.vy0, &self.vy, visc, cfg);
+
+    stats.accumulate(Fluid::project(g, obs, &mut self.vx0, &mut self.vy0, &mut self.p, &mut self.div, ps, cfg));
+
+    advection::advect(g, obs, Field::VelocityX, advect, &mut self.vx, &self.vx0, &self.vx0, &self.vy0, dt);
+    advection::advect(g, obs, Field::VelocityY, advect, &mut self.vy, &self.vy0, &self.vx0, &self.vy0, dt);
+
+    stats.accumulate(Fluid::project(g, obs, &mut self.vx, &mut self.vy, &mut self.p, &mut self.div, ps, cfg));
+
+    Fluid::diffuse(g, obs, Field::Scalar, &mut self.s, &self.density, diff, cfg);
+    advection::advect(g, obs, Field::Scalar, advect, &mut self.density, &self.s, &self.vx, &self.vy, dt);
+
+    Fluid::diffuse(g, obs, Field::Scalar, &mut self.s, &self.temperature, diff, cfg);
+    advection::advect(g, obs, Field::Scalar, advect, &mut self.temperature, &self.s, &self.vx, &self.vy, dt);
+
+    for field in self.scalars.iter_mut() {
+      Fluid::diffuse(g, obs, Field::Scalar, &mut self.s, &field.values, field.diffusion * dt, cfg);
+      advection::advect(g, obs, Field::Scalar, advect, &mut field.values, &self.s, &self.vx, &self.vy, dt);
+      fade(&mut field.values, field.decay, dt);
+    }
+    fade(&mut self.density, self.density_decay, dt);
+    fade(&mut self.temperature, self.temperature_decay, dt);
+    fade(&mut self.vx, self.velocity_decay, dt);
+    fade(&mut self.vy, self.velocity_decay, dt);
+    stats
+  }
+
+  fn add_body(&mut self, body: Body) -> u32 {
+    let id = self.obstacles.add_body(self.grid, body);
+    self.forces.push(VecDeque::new());
+    for k in 0..self.grid.cells() {
+      if self.obstacles.is_solid(k) {
+        self.clear_scalars(k);
+      }
+    }
+    id
+  }
+
+  fn clear_scalars(&mut self, k: usize) {
+    self.density[k] = 0f64;
+    self.temperature[k] = 0f64;
+    for field in self.scalars.iter_mut() {
+      field.values[k] = 0f64;
+    }
+  }
 
-        let s1 = x - i0; 
-        let s0 = 1.0f64 - s1; 
-        let t1 = y - j0; 
-        let t0 = 1.0f64 - t1;
+  fn diffuse(g: Grid, obs: &Obstacles, b: Field, x: &mut [f64], x0: &[f64], rate: f64, cfg: SolverConfig) {
+    let a: f64 = rate * g.scale() * g.scale();
+    Fluid::lin_solve(g, obs, b, x, x0, a, 1f64 + 4f64 * a, cfg);
+  }
 
-        let i0i = i0 as i32;
-        let i1i = i1 as i32;
-        let j0i = j0 as i32;
-        let j1i = j1 as i32;
+  /// Sum of the 4 neighbours of `(i, j)` and how many of them were left out
+  /// because they are solid and `b` must not flow into obstacles.
+  fn neighbours(g: Grid, obs: &Obstacles, b: Field, x: &[f64], i: i32, j: i32) -> (f64, f64) {
+    let zero_flux = obs.any() && (b == Field::Scalar || b == Field::Pressure);
+    let mut sum = 0f64;
+    let mut blocked = 0f64;
+    for (ni, nj) in [(i + 1, j), (i - 1, j), (i, j + 1), (i, j - 1)] {
+      let k = g.ix(ni, nj);
+      if zero_flux && obs.is_solid(k) {
+        blocked += 1f64;
+      } else {
+        sum += x[k];
+      }
+    }
+    (sum, blocked)
+  }
 
-        d[IX(i, j)] = s0 * ( t0 * d0[IX(i0i, j0i)] + t1 * d0[IX(i0i, j1i)])
-          + s1 * ( t0 * d0[IX(i1i, j0i)] + t1 * d0[IX(i1i, j1i)]);
+  /// Gauss-Seidel solve of `c * x - a * (sum of the 4 neighbours of x) = x0`,
+  /// sweeping until the relative residual drops below `cfg.tolerance`.
+  /// Solid cells are skipped, and for scalars and pressure the faces they
+  /// share with fluid cells carry no flux.
+  #[allow(clippy::too_many_arguments)]
+  fn lin_solve(
+    g: Grid,
+    obs: &Obstacles,
+    b: Field,
+    x: &mut [f64],
+    x0: &[f64],
+    a: f64,
+    c: f64,
+    cfg: SolverConfig,
+  ) -> SolveStats {
+    let mut stats = SolveStats {
+      iterations: 0,
+      residual: Fluid::residual(g, obs, b, x, x0, a, c),
+    };
+    while stats.iterations < cfg.max_iterations && stats.residual > cfg.tolerance {
+      for j in 1..(g.height - 1) {
+        for i in 1..(g.width - 1) {
+          let k = g.ix(i, j);
+          if obs.is_solid(k) {
+            continue;
+          }
+          let (sum, blocked) = Fluid::neighbours(g, obs, b, x, i, j);
+          // a fluid cell walled in on all sides has no pressure equation
+          let diag = c - a * blocked;
+          if diag > 0f64 {
+            x[k] = (x0[k] + a * sum) / diag;
+          }
+        }
       }
+      set_bnd(g, obs, b, x);
+      stats.iterations += 1;
+      stats.residual = Fluid::residual(g, obs, b, x, x0, a, c);
     }
+    stats
+  }
 
-    Fluid::set_bnd(b, d);
+  /// L2 norm of the residual of the `lin_solve` system over the fluid cells,
+  /// relative to the norm of the right-hand side (absolute when that is zero).
+  fn residual(g: Grid, obs: &Obstacles, b: Field, x: &[f64], x0: &[f64], a: f64, c: f64) -> f64 {
+    let mut r2 = 0f64;
+    let mut b2 = 0f64;
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        let k = g.ix(i, j);
+        if obs.is_solid(k) {
+          continue;
+        }
+        let (sum, blocked) = Fluid::neighbours(g, obs, b, x, i, j);
+        let diag = c - a * blocked;
+        if diag <= 0f64 {
+          continue;
+        }
+        let r = x0[k] + a * sum - diag * x[k];
+        r2 += r * r;
+        b2 += x0[k] * x0[k];
+      }
+    }
+    if b2 > 0f64 {
+      (r2 / b2).sqrt()
+    } else {
+      r2.sqrt()
+    }
   }
 
+  #[allow(clippy::too_many_arguments)]
+  fn project(
+    g: Grid,
+    obs: &Obstacles,
+    veloc_x: &mut [f64],
+    veloc_y: &mut [f64],
+    p: &mut [f64],
+    div: &mut [f64],
+    ps: PressureSolver,
+    cfg: SolverConfig,
+  ) -> SolveStats {
+    let scale = g.scale();
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        div[g.ix(i, j)] = -0.5f64
+          * ((veloc_x[g.ix(i + 1, j)] - veloc_x[g.ix(i - 1, j)]) / scale
+            + (veloc_y[g.ix(i, j + 1)] - veloc_y[g.ix(i, j - 1)]) / scale);
+        p[g.ix(i, j)] = 0f64;
+      }
+    }
+    set_bnd(g, obs, Field::Scalar, div);
+    set_bnd(g, obs, Field::Pressure, p);
+    let periodic = g.boundaries.periodic_x() && g.boundaries.periodic_y();
+    let stats = match ps {
+      PressureSolver::GaussSeidel => Fluid::lin_solve(g, obs, Field::Pressure, p, div, 1f64, 4f64, cfg),
+      PressureSolver::Multigrid => multigrid::solve(g, obs, p, div, cfg),
+      PressureSolver::ConjugateGradient => pcg::solve(g, obs, p, div, cfg),
+      PressureSolver::Spectral if periodic && !obs.any() => spectral::solve(g, p, div),
+      PressureSolver::Spectral => multigrid::solve(g, obs, p, div, cfg),
+    };
+    set_bnd(g, obs, Field::Pressure, p);
+
+    // an obstacle face has no pressure gradient across it
+    let p_at = |k: usize, from: usize| if obs.is_solid(k) { p[from] } else { p[k] };
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        let k = g.ix(i, j);
+        if obs.is_solid(k) {
+          continue;
+        }
+        veloc_x[k] -= 0.5f64 * (p_at(g.ix(i + 1, j), k) - p_at(g.ix(i - 1, j), k)) * scale;
+        veloc_y[k] -= 0.5f64 * (p_at(g.ix(i, j + 1), k) - p_at(g.ix(i, j - 1), k)) * scale;
+      }
+    }
+    set_bnd(g, obs, Field::VelocityX, veloc_x);
+    set_bnd(g, obs, Field::VelocityY, veloc_y);
+    stats
+  }
 
-  fn set_bnd(b: i32, x: &mut Vec<f64>) {
+  /// Boussinesq buoyancy: warm fluid accelerates up the screen (towards
+  /// smaller y) and dense smoke is pulled down.
+  #[allow(clippy::too_many_arguments)]
+  fn apply_buoyancy(
+    g: Grid,
+    obs: &Obstacles,
+    veloc_y: &mut [f64],
+    temperature: &[f64],
+    density: &[f64],
+    buoyancy: f64,
+    weight: f64,
+    dt: f64,
+  ) {
+    for k in 0..g.cells() {
+      if !obs.is_solid(k) {
+        veloc_y[k] -= dt * (buoyancy * temperature[k] - weight * density[k]);
+      }
+    }
+    set_bnd(g, obs, Field::VelocityY, veloc_y);
+  }
 
-    for i in 1..(N - 1) {
-      x[IX(i, 0)] = if b == 2 { -1f64 * x[IX(i, 1)]}else{ x[IX(i, 1)]};
-      x[IX(i, N-1)] =if b == 2 { -1f64 * x[IX(i, N-2)] }else{ x[IX(i, N-2)]};
+  /// Adds the uniform `force` and the optional per-cell `map` to the velocity.
+  fn apply_forces(
+    g: Grid,
+    obs: &Obstacles,
+    veloc_x: &mut [f64],
+    veloc_y: &mut [f64],
+    force: (f64, f64),
+    map: Option<&(Vec<f64>, Vec<f64>)>,
+    dt: f64,
+  ) {
+    if force == (0f64, 0f64) && map.is_none() {
+      return;
+    }
+    for k in 0..g.cells() {
+      if obs.is_solid(k) {
+        continue;
+      }
+      let (mx, my) = map.map_or((0f64, 0f64), |(fx, fy)| (fx[k], fy[k]));
+      veloc_x[k] += dt * (force.0 + mx);
+      veloc_y[k] += dt * (force.1 + my);
     }
+    set_bnd(g, obs, Field::VelocityX, veloc_x);
+    set_bnd(g, obs, Field::VelocityY, veloc_y);
+  }
 
-    for j in 1..(N - 1) {
-       x[IX(0, j)] = if b == 1 { -1f64 * x[IX(1, j)] }else{  x[IX(1, j)] };
-       x[IX(N-1, j)] = if b == 1 { -1f64 * x[IX(N-2, j)] }else{  x[IX(N-2, j)]};
+  /// Pushes the velocity towards the centre of each swirl, putting back the
+  /// small-scale rotation that `advect` and `diffuse` smear out. `curl` is
+  /// scratch space for the vorticity.
+  fn confine(
+    g: Grid,
+    obs: &Obstacles,
+    veloc_x: &mut [f64],
+    veloc_y: &mut [f64],
+    curl: &mut [f64],
+    strength: f64,
+    dt: f64,
+  ) {
+    let scale = g.scale();
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        curl[g.ix(i, j)] = 0.5f64
+          * ((veloc_y[g.ix(i + 1, j)] - veloc_y[g.ix(i - 1, j)]) - (veloc_x[g.ix(i, j + 1)] - veloc_x[g.ix(i, j - 1)]))
+          * scale;
+      }
     }
+    set_bnd(g, obs, Field::Scalar, curl);
 
-    x[IX(0, 0)] = 0.5f64 * (x[IX(1, 0)] + x[IX(0, 1)]);
-    x[IX(0, N-1)] = 0.5f64 * (x[IX(1, N-1)] + x[IX(0, N-2)]);
-    x[IX(N-1, 0)] = 0.5f64 * (x[IX(N-2, 0)] + x[IX(N-1, 1)]);
-    x[IX(N-1, N-1)] = 0.5f64 * (x[IX(N-2, N-1)] + x[IX(N-1, N-2)]);
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        let k = g.ix(i, j);
+        if obs.is_solid(k) {
+          continue;
+        }
+        let nx = 0.5f64 * (curl[g.ix(i + 1, j)].abs() - curl[g.ix(i - 1, j)].abs());
+        let ny = 0.5f64 * (curl[g.ix(i, j + 1)].abs() - curl[g.ix(i, j - 1)].abs());
+        let length = (nx * nx + ny * ny).sqrt();
+        if length < 1e-12 {
+          continue;
+        }
+        let f = strength / scale * curl[k] / length;
+        veloc_x[k] += dt * f * ny;
+        veloc_y[k] -= dt * f * nx;
+      }
+    }
+    set_bnd(g, obs, Field::VelocityX, veloc_x);
+    set_bnd(g, obs, Field::VelocityY, veloc_y);
   }
 
   // void renderD() {
@@ -242,13 +1108,6 @@ impl Fluid{
   //     }
   //   }
   // }
-
-  // void fadeD() {
-  //   for (int i=0; i<this.density.length; i++) {
-  //     float d = density[i];
-  //     density[i] = constrain(d-0.1, 0, 10000);
-  //   }
-  // }
 }
 
 
@@ -0,0 +1,60 @@
+use crate::fluid::Grid;
+use crate::obstacle::Obstacles;
+use wasm_bindgen::prelude::*;
+
+/// Force the fluid exerts on an obstacle during one step, per unit depth with
+/// a fluid density of 1.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Force {
+  /// Component along +x, the direction of a left-to-right stream.
+  pub drag: f64,
+  /// Component along -y, i.e. up the screen.
+  pub lift: f64,
+}
+
+/// Integrates pressure and viscous shear over every face between body `id`
+/// and a fluid cell. `p` is the pressure `Fluid::project` solved for, which
+/// is the physical pressure times `dt`; `visc` is the kinematic viscosity.
+#[allow(clippy::too_many_arguments)]
+pub fn measure(
+  g: Grid,
+  obs: &Obstacles,
+  id: u32,
+  p: &[f64],
+  vx: &[f64],
+  vy: &[f64],
+  visc: f64,
+  dt: f64,
+) -> Force {
+  let h = 1f64 / g.scale();
+  let (mut fx, mut fy) = (0f64, 0f64);
+  for j in 1..(g.height - 1) {
+    for i in 1..(g.width - 1) {
+      let k = g.ix(i, j);
+      if obs.is_solid(k) {
+        continue;
+      }
+      for (nx, ny) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+        // (nx, ny) is the outward normal of the body face shared with (i, j)
+        let kk = g.ix(i - nx, j - ny);
+        if obs.owner(kk) != Some(id) {
+          continue;
+        }
+        let (nx, ny) = (nx as f64, ny as f64);
+        let pressure = p[k] / dt;
+        fx -= pressure * nx * h;
+        fy -= pressure * ny * h;
+
+        // shear over half a cell on either side of the face; the solid cell
+        // holds the velocity of the body surface
+        let (wx, wy) = obs.velocity(kk);
+        let (tx, ty) = (-ny, nx);
+        let slip = (vx[k] - wx) * tx + (vy[k] - wy) * ty;
+        fx += visc * slip * tx;
+        fy += visc * slip * ty;
+      }
+    }
+  }
+  Force { drag: fx, lift: -fy }
+}
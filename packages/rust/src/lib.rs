@@ -1,11 +1,29 @@
+mod advection;
+mod boundary;
+mod error;
 mod fluid;
+mod force;
+mod mac;
+mod multigrid;
+mod obstacle;
+mod pcg;
+mod poisson;
+mod scalar;
+mod solver;
+mod spectral;
 mod utils;
-use rust_decimal::prelude::*;
-use rust_decimal_macros::dec;
 // use std::convert::TryInto;
 
 use wasm_bindgen::prelude::*;
 
+pub use advection::{sample, Advection, Backtrace, Interpolation};
+pub use boundary::{Boundaries, Boundary, Edge, Side};
+pub use error::FluidError;
+pub use fluid::{Fluid, Grid};
+pub use force::Force;
+pub use mac::MacFluid;
+pub use solver::{PressureSolver, SolveStats, SolverConfig};
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
@@ -41,14 +59,18 @@ extern "C" {
     pub type ArrayOfNumbers;
 }
 
+/// The wasm module's memory, for building typed-array views from the
+/// `*_ptr` exports of `Fluid`.
+#[wasm_bindgen]
+pub fn wasm_memory() -> JsValue {
+    wasm_bindgen::memory()
+}
+
 #[wasm_bindgen(js_name = "addArray")]
 pub fn add_array(arr: ArrayOfNumbers) -> u32 {
+    #[allow(deprecated)]
     let rust_arr: Vec<u32> = arr.into_serde().unwrap();
-    let mut sum: u32 = 0;
-    for element in rust_arr {
-        sum = element + sum
-    }
-    return sum;
+    rust_arr.iter().sum()
 }
 
 #[wasm_bindgen(js_name = "helloWorld")]
@@ -58,72 +80,3 @@ pub fn hello_world(name: Option<String>) {
         None => alert(String::from("Hello world!")),
     }
 }
-
-use lazy_static::lazy_static; // 1.4.0
-use std::sync::Mutex;
-
-lazy_static! {
-    static ref FLUID_INSTANCE: Mutex<fluid::Fluid> =
-        Mutex::new(fluid::Fluid::create(0.05f64, 0.00001f64, 0f64));
-}
-
-#[wasm_bindgen(js_name = "create_fluid")]
-pub fn create_fluid(size: Option<i32>) {
-    let mut tmp = FLUID_INSTANCE.lock().unwrap();
-
-    // 41 mins to render
-    *tmp = fluid::Fluid::create(0.05f64, 0.00001f64, 0f64);
-    // tmp.step();
-    // log("initial creation log");
-    // log_u32(tmp.size as u32);
-    // let tmp1 = Decimal::from_i32((tmp.size - 2).into()).unwrap_or(dec!(8888));
-    // log_u32(tmp1.to_u32().unwrap_or(9999));
-    // log("finish initial creation log");
-}
-
-#[wasm_bindgen(js_name = "fluid_step")]
-pub fn fluid_step() {
-    let mut tmp = FLUID_INSTANCE.lock().unwrap();
-    tmp.step();
-}
-
-#[wasm_bindgen(js_name = "fluid_add_density")]
-pub fn fluid_add_density(x: Option<i32>, y: Option<i32>, amount: Option<f64>) {
-    let mut tmp = FLUID_INSTANCE.lock().unwrap();
-    tmp.addDensity(
-        x.unwrap(),
-        y.unwrap(),
-        amount.unwrap(),
-    );
-}
-
-#[wasm_bindgen(js_name = "fluid_add_velocity")]
-pub fn fluid_add_velocity(x: Option<i32>, y: Option<i32>, vx: Option<f64>, vy: Option<f64>) {
-    let mut tmp = FLUID_INSTANCE.lock().unwrap();
-    tmp.addVelocity(x.unwrap(), y.unwrap(), vx.unwrap(), vy.unwrap());
-}
-
-#[wasm_bindgen(js_name = "fluid_get_density")]
-pub fn fluid_get_density() -> Vec<f64> {
-    let tmp = FLUID_INSTANCE.lock().unwrap();
-    return tmp.density.clone();
-}
-
-// #[wasm_bindgen(js_name = "fluid_get_velocity")]
-// pub fn fluid_get_velocity() -> Vec<f64> {
-//     let tmp = FLUID_INSTANCE.lock().unwrap();
-//     return tmp
-//         .vel
-//         .iter()
-//         .map(|v| {
-//             if (v[0] + v[1]) > dec!(0.001) {
-//                 (v[0] + v[1]).to_f64().unwrap()
-//             } else {
-//                 0f64
-//             }
-//             // (v[0] + v[1]).to_f64().unwrap()
-//             // v.to_f64().unwrap()
-//         })
-//         .collect();
-//     // get_density(x.unwrap(),y.unwrap()).to_f64().unwrap()
-// }
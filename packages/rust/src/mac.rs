@@ -0,0 +1,444 @@
+use crate::boundary::{Boundaries, Boundary, Side};
+use crate::error::FluidError;
+use crate::fluid::{check, Grid};
+use crate::multigrid;
+use crate::obstacle::Obstacles;
+use crate::pcg;
+use crate::poisson::{norm, Laplacian};
+use crate::solver::{PressureSolver, SolveStats, SolverConfig};
+use wasm_bindgen::prelude::*;
+
+/// Where a field's values sit in their cell. `u[ix(i, j)]` is the velocity
+/// through the left face of cell `(i, j)` and `v[ix(i, j)]` the one through
+/// its top face, so the last face of each row or column lives in the ghost
+/// cell beyond it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Layout {
+  Centre,
+  LeftFace,
+  TopFace,
+}
+
+impl Layout {
+  /// Offset of the stored value from the cell centre.
+  fn offset(self) -> (f64, f64) {
+    match self {
+      Layout::Centre => (0f64, 0f64),
+      Layout::LeftFace => (-0.5f64, 0f64),
+      Layout::TopFace => (0f64, -0.5f64),
+    }
+  }
+
+  /// Whether the values are normal to faces crossing the x and y axes.
+  fn normal(self) -> (bool, bool) {
+    (self == Layout::LeftFace, self == Layout::TopFace)
+  }
+}
+
+/// Valid indices along one axis with `n` interior cells. Faces normal to the
+/// axis run from the first wall to the last, `1..=n + 1`; everything else
+/// covers the interior. A periodic axis has `n` distinct values of either
+/// kind, and the face at `n + 1` repeats the one at `1`.
+#[derive(Clone, Copy)]
+struct Axis {
+  n: i32,
+  normal: bool,
+  periodic: bool,
+}
+
+impl Axis {
+  fn last(self) -> i32 {
+    if self.normal && !self.periodic {
+      self.n + 1
+    } else {
+      self.n
+    }
+  }
+
+  /// Clamps or wraps an index into the valid range. Clamping a tangential
+  /// neighbour repeats the value, which is what a free-slip wall does.
+  fn index(self, v: i32) -> i32 {
+    if self.periodic {
+      (v - 1).rem_euclid(self.n) + 1
+    } else {
+      v.clamp(1, self.last())
+    }
+  }
+
+  /// Indices that are unknowns of the solves; walls are held at zero.
+  fn unknowns(self) -> std::ops::RangeInclusive<i32> {
+    if self.normal && !self.periodic {
+      2..=self.n
+    } else {
+      1..=self.n
+    }
+  }
+
+  /// Lower index and weight of the upper one for a position in index space.
+  fn locate(self, v: f64) -> (i32, i32, f64) {
+    if self.periodic {
+      let v = (v - 1f64).rem_euclid(self.n as f64) + 1f64;
+      let i0 = v.floor() as i32;
+      (i0, self.index(i0 + 1), v - i0 as f64)
+    } else {
+      let last = self.last();
+      let v = v.clamp(1f64, last as f64);
+      let i0 = (v.floor() as i32).min((last - 1).max(1));
+      (i0, (i0 + 1).min(last), v - i0 as f64)
+    }
+  }
+}
+
+fn axes(g: Grid, layout: Layout) -> (Axis, Axis) {
+  let (normal_x, normal_y) = layout.normal();
+  (
+    Axis {
+      n: g.width - 2,
+      normal: normal_x,
+      periodic: g.boundaries.periodic_x(),
+    },
+    Axis {
+      n: g.height - 2,
+      normal: normal_y,
+      periodic: g.boundaries.periodic_y(),
+    },
+  )
+}
+
+/// Bilinear interpolation of `f` at `(x, y)`, in cells.
+fn sample(g: Grid, f: &[f64], layout: Layout, x: f64, y: f64) -> f64 {
+  let (ax, ay) = axes(g, layout);
+  let (ox, oy) = layout.offset();
+  let (i0, i1, s) = ax.locate(x - ox);
+  let (j0, j1, t) = ay.locate(y - oy);
+  (1f64 - s) * ((1f64 - t) * f[g.ix(i0, j0)] + t * f[g.ix(i0, j1)])
+    + s * ((1f64 - t) * f[g.ix(i1, j0)] + t * f[g.ix(i1, j1)])
+}
+
+/// Zeroes the wall faces and copies the repeated face of periodic axes.
+fn enforce(g: Grid, u: &mut [f64], v: &mut [f64]) {
+  let (nx, ny) = (g.width - 2, g.height - 2);
+  for j in 1..=ny {
+    let (first, last) = (g.ix(1, j), g.ix(nx + 1, j));
+    if g.boundaries.periodic_x() {
+      u[last] = u[first];
+    } else {
+      u[first] = 0f64;
+      u[last] = 0f64;
+    }
+  }
+  for i in 1..=nx {
+    let (first, last) = (g.ix(i, 1), g.ix(i, ny + 1));
+    if g.boundaries.periodic_y() {
+      v[last] = v[first];
+    } else {
+      v[first] = 0f64;
+      v[last] = 0f64;
+    }
+  }
+}
+
+/// A fluid on a staggered (MAC) grid: each velocity component lives on the
+/// faces it flows through instead of at the cell centre. Pressure and
+/// divergence then couple neighbouring cells directly, so the projection
+/// leaves no checkerboard modes. The edges are free-slip walls unless made
+/// periodic.
+#[wasm_bindgen]
+pub struct MacFluid {
+  grid: Grid,
+  dt: f64,
+  diff: f64,
+  visc: f64,
+
+  density: Vec<f64>,
+  u: Vec<f64>,
+  v: Vec<f64>,
+  scratch: Vec<f64>,
+  p: Vec<f64>,
+  div: Vec<f64>,
+
+  solver: SolverConfig,
+  pressure_solver: PressureSolver,
+  stats: SolveStats,
+}
+
+#[wasm_bindgen]
+impl MacFluid {
+  #[wasm_bindgen(constructor)]
+  pub fn new(width: i32, height: i32, dt: f64, diffusion: f64, viscosity: f64) -> Result<MacFluid, FluidError> {
+    let grid = Grid::checked(width, height)?;
+    let cells = grid.cells();
+    Ok(MacFluid {
+      grid,
+      dt: check("dt", dt, false)?,
+      diff: check("diffusion", diffusion, true)?,
+      visc: check("viscosity", viscosity, true)?,
+      density: vec![0f64; cells],
+      u: vec![0f64; cells],
+      v: vec![0f64; cells],
+      scratch: vec![0f64; cells],
+      p: vec![0f64; cells],
+      div: vec![0f64; cells],
+      solver: SolverConfig::default(),
+      pressure_solver: PressureSolver::Multigrid,
+      stats: SolveStats::default(),
+    })
+  }
+
+  /// Advects the velocity, diffuses it, makes it divergence-free and then
+  /// carries the density along.
+  pub fn step(&mut self) {
+    let g = self.grid;
+    let (dt, cfg) = (self.dt, self.solver);
+    enforce(g, &mut self.u, &mut self.v);
+
+    let (u0, v0) = (self.u.clone(), self.v.clone());
+    advect(g, Layout::LeftFace, &mut self.u, &u0, &u0, &v0, dt);
+    advect(g, Layout::TopFace, &mut self.v, &v0, &u0, &v0, dt);
+    diffuse(g, Layout::LeftFace, &mut self.u, &mut self.scratch, self.visc * dt, cfg);
+    diffuse(g, Layout::TopFace, &mut self.v, &mut self.scratch, self.visc * dt, cfg);
+    enforce(g, &mut self.u, &mut self.v);
+    self.stats = self.project();
+
+    self.scratch.copy_from_slice(&self.density);
+    advect(g, Layout::Centre, &mut self.density, &self.scratch, &self.u, &self.v, dt);
+    diffuse(g, Layout::Centre, &mut self.density, &mut self.scratch, self.diff * dt, cfg);
+  }
+
+  pub fn add_density(&mut self, x: i32, y: i32, amount: f64) {
+    let index = self.grid.ix(x, y);
+    self.density[index] += amount;
+  }
+
+  /// Adds `(amount_x, amount_y)` of momentum to cell `(x, y)`, split
+  /// between the faces on either side of it, so filling every cell gives a
+  /// uniform velocity of that amount.
+  pub fn add_velocity(&mut self, x: i32, y: i32, amount_x: f64, amount_y: f64) {
+    let g = self.grid;
+    let (x, y) = (x.clamp(1, g.width - 2), y.clamp(1, g.height - 2));
+    let (ux, _) = axes(g, Layout::LeftFace);
+    let (_, vy) = axes(g, Layout::TopFace);
+    self.u[g.ix(x, y)] += 0.5f64 * amount_x;
+    self.u[g.ix(ux.index(x + 1), y)] += 0.5f64 * amount_x;
+    self.v[g.ix(x, y)] += 0.5f64 * amount_y;
+    self.v[g.ix(x, vy.index(y + 1))] += 0.5f64 * amount_y;
+    enforce(g, &mut self.u, &mut self.v);
+  }
+
+  /// Makes the left and right edges, and the top and bottom ones, wrap
+  /// around instead of being walls.
+  pub fn set_periodic(&mut self, x: bool, y: bool) {
+    let kind = |periodic: bool| if periodic { Boundary::Periodic } else { Boundary::FreeSlip };
+    let mut boundaries = Boundaries::default();
+    boundaries.set(Side::Left, kind(x));
+    boundaries.set(Side::Top, kind(y));
+    self.grid.boundaries = boundaries;
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn width(&self) -> i32 {
+    self.grid.width
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn height(&self) -> i32 {
+    self.grid.height
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn density(&self) -> Vec<f64> {
+    self.density.clone()
+  }
+
+  /// Horizontal velocity averaged to the cell centres, laid out like
+  /// `density`.
+  #[wasm_bindgen(getter)]
+  pub fn velocity_x(&self) -> Vec<f64> {
+    self.centred(Layout::LeftFace)
+  }
+
+  /// Vertical velocity averaged to the cell centres.
+  #[wasm_bindgen(getter)]
+  pub fn velocity_y(&self) -> Vec<f64> {
+    self.centred(Layout::TopFace)
+  }
+
+  /// Net outflow of every cell, in the units `Fluid` uses for its
+  /// divergence; zero up to the solver tolerance after a step.
+  #[wasm_bindgen(getter)]
+  pub fn divergence(&self) -> Vec<f64> {
+    let g = self.grid;
+    let mut div = vec![0f64; g.cells()];
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        div[g.ix(i, j)] = (self.u[g.ix(i + 1, j)] - self.u[g.ix(i, j)] + self.v[g.ix(i, j + 1)] - self.v[g.ix(i, j)])
+          * g.scale();
+      }
+    }
+    div
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn solver(&self) -> SolverConfig {
+    self.solver
+  }
+
+  /// Needs at least one iteration and a finite, non-negative tolerance.
+  pub fn set_solver(&mut self, solver: SolverConfig) -> Result<(), FluidError> {
+    self.solver = solver.checked()?;
+    Ok(())
+  }
+
+  /// Spectral projection solves the collocated stencil, so here it falls
+  /// back to multigrid.
+  #[wasm_bindgen(getter)]
+  pub fn pressure_solver(&self) -> PressureSolver {
+    self.pressure_solver
+  }
+
+  #[wasm_bindgen(setter)]
+  pub fn set_pressure_solver(&mut self, pressure_solver: PressureSolver) {
+    self.pressure_solver = pressure_solver;
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn solve_stats(&self) -> SolveStats {
+    self.stats
+  }
+}
+
+impl MacFluid {
+  pub fn grid(&self) -> Grid {
+    self.grid
+  }
+
+  fn centred(&self, layout: Layout) -> Vec<f64> {
+    let g = self.grid;
+    let f = if layout == Layout::LeftFace { &self.u } else { &self.v };
+    let mut out = vec![0f64; g.cells()];
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        out[g.ix(i, j)] = sample(g, f, layout, i as f64, j as f64);
+      }
+    }
+    out
+  }
+
+  /// Solves for the pressure that removes the divergence of the face
+  /// velocities and subtracts its gradient across every face. The compact
+  /// stencil this needs is exactly the operator `Laplacian` builds.
+  fn project(&mut self) -> SolveStats {
+    let g = self.grid;
+    let scale = g.scale();
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        let k = g.ix(i, j);
+        self.div[k] = -(self.u[g.ix(i + 1, j)] - self.u[k] + self.v[g.ix(i, j + 1)] - self.v[k]) / scale;
+        self.p[k] = 0f64;
+      }
+    }
+    // the solvers take an obstacle mask; this grid has no solid cells
+    let (obs, cfg) = (&Obstacles::new(g), self.solver);
+    let stats = match self.pressure_solver {
+      PressureSolver::GaussSeidel => gauss_seidel(g, obs, &mut self.p, &self.div, cfg),
+      PressureSolver::ConjugateGradient => pcg::solve(g, obs, &mut self.p, &self.div, cfg),
+      PressureSolver::Multigrid | PressureSolver::Spectral => multigrid::solve(g, obs, &mut self.p, &self.div, cfg),
+    };
+
+    let (ux, uy) = axes(g, Layout::LeftFace);
+    let (vx, vy) = axes(g, Layout::TopFace);
+    let (cx, cy) = axes(g, Layout::Centre);
+    for j in uy.unknowns() {
+      for i in ux.unknowns() {
+        self.u[g.ix(i, j)] -= (self.p[g.ix(i, j)] - self.p[g.ix(cx.index(i - 1), j)]) * scale;
+      }
+    }
+    for j in vy.unknowns() {
+      for i in vx.unknowns() {
+        self.v[g.ix(i, j)] -= (self.p[g.ix(i, j)] - self.p[g.ix(i, cy.index(j - 1))]) * scale;
+      }
+    }
+    enforce(g, &mut self.u, &mut self.v);
+    stats
+  }
+}
+
+/// Semi-Lagrangian advection of `d0` into `d`, tracing back from where each
+/// value is stored with a midpoint (RK2) step through the face velocities.
+fn advect(g: Grid, layout: Layout, d: &mut [f64], d0: &[f64], u: &[f64], v: &[f64], dt: f64) {
+  let dt0 = dt * g.scale();
+  let (ax, ay) = axes(g, layout);
+  let (ox, oy) = layout.offset();
+  let velocity = |x: f64, y: f64| {
+    (
+      sample(g, u, Layout::LeftFace, x, y),
+      sample(g, v, Layout::TopFace, x, y),
+    )
+  };
+  for j in ay.unknowns() {
+    for i in ax.unknowns() {
+      let (x, y) = (i as f64 + ox, j as f64 + oy);
+      let (vx, vy) = velocity(x, y);
+      let (mx, my) = velocity(x - 0.5f64 * dt0 * vx, y - 0.5f64 * dt0 * vy);
+      d[g.ix(i, j)] = sample(g, d0, layout, x - dt0 * mx, y - dt0 * my);
+    }
+  }
+}
+
+/// Implicit diffusion of `x` by `rate`, with Gauss-Seidel sweeps until the
+/// update falls below the solver tolerance. `x0` is scratch space.
+fn diffuse(g: Grid, layout: Layout, x: &mut [f64], x0: &mut [f64], rate: f64, cfg: SolverConfig) {
+  if rate <= 0f64 {
+    return;
+  }
+  let a = rate * g.scale() * g.scale();
+  let (ax, ay) = axes(g, layout);
+  x0.copy_from_slice(x);
+  let scale = x0.iter().fold(0f64, |m, v| m.max(v.abs())).max(1e-12);
+  for _ in 0..cfg.max_iterations {
+    let mut change = 0f64;
+    for j in ay.unknowns() {
+      for i in ax.unknowns() {
+        let k = g.ix(i, j);
+        let sum = x[g.ix(ax.index(i - 1), j)]
+          + x[g.ix(ax.index(i + 1), j)]
+          + x[g.ix(i, ay.index(j - 1))]
+          + x[g.ix(i, ay.index(j + 1))];
+        let next = (x0[k] + a * sum) / (1f64 + 4f64 * a);
+        change = change.max((next - x[k]).abs());
+        x[k] = next;
+      }
+    }
+    if change <= cfg.tolerance * scale {
+      break;
+    }
+  }
+}
+
+/// Plain Gauss-Seidel on the pressure operator, for comparison with the
+/// faster solvers.
+fn gauss_seidel(g: Grid, obs: &Obstacles, p: &mut [f64], div: &[f64], cfg: SolverConfig) -> SolveStats {
+  let a = Laplacian::for_grid(g, obs);
+  let (mut x, mut b, mut r) = (vec![0f64; a.len()], vec![0f64; a.len()], vec![0f64; a.len()]);
+  a.gather_rhs(g, div, &mut b);
+  a.gather(g, p, &mut x);
+  if a.singular() {
+    a.remove_mean(&mut b);
+  }
+  let b_norm = norm(&b);
+  let relative = |r: f64| if b_norm > 0f64 { r / b_norm } else { r };
+
+  a.residual(&x, &b, &mut r);
+  let mut stats = SolveStats {
+    iterations: 0,
+    residual: relative(norm(&r)),
+  };
+  while stats.iterations < cfg.max_iterations && stats.residual > cfg.tolerance {
+    a.smooth(&mut x, &b, 1);
+    a.residual(&x, &b, &mut r);
+    stats.iterations += 1;
+    stats.residual = relative(norm(&r));
+  }
+  a.scatter(g, &x, p);
+  stats
+}
@@ -0,0 +1,152 @@
+use crate::fluid::Grid;
+use crate::obstacle::Obstacles;
+use crate::poisson::{norm, Laplacian};
+use crate::solver::{SolveStats, SolverConfig};
+
+const PRE_SMOOTH: usize = 2;
+const POST_SMOOTH: usize = 2;
+const COARSEST_SWEEPS: usize = 50;
+
+/// One level of the multigrid hierarchy.
+struct Level {
+  a: Laplacian,
+  x: Vec<f64>,
+  b: Vec<f64>,
+  r: Vec<f64>,
+}
+
+impl Level {
+  fn new(a: Laplacian) -> Level {
+    let n = a.len();
+    Level {
+      a,
+      x: vec![0f64; n],
+      b: vec![0f64; n],
+      r: vec![0f64; n],
+    }
+  }
+
+  /// Coarsen by merging 2x2 blocks; an edge between two coarse cells carries
+  /// the fine edges crossing it, halved to keep the unit stencil.
+  fn coarsen(&self) -> Level {
+    let a = &self.a;
+    let cnx = a.nx.div_ceil(2);
+    let cny = a.ny.div_ceil(2);
+    let mut east = vec![0f64; cnx * cny];
+    let mut north = vec![0f64; cnx * cny];
+    let mut fixed = vec![0f64; cnx * cny];
+    for j in 0..a.ny {
+      for i in 0..a.nx {
+        let k = i + j * a.nx;
+        let ck = i / 2 + (j / 2) * cnx;
+        if i % 2 == 1 || (a.wrap_x && i + 1 == a.nx) {
+          east[ck] += 0.5 * a.east[k];
+        }
+        if j % 2 == 1 || (a.wrap_y && j + 1 == a.ny) {
+          north[ck] += 0.5 * a.north[k];
+        }
+        fixed[ck] += 0.5 * a.fixed[k];
+      }
+    }
+    Level::new(Laplacian::new(cnx, cny, (a.wrap_x, a.wrap_y), east, north, fixed))
+  }
+
+  fn smooth(&mut self, sweeps: usize) {
+    self.a.smooth(&mut self.x, &self.b, sweeps);
+  }
+
+  fn compute_residual(&mut self) {
+    self.a.residual(&self.x, &self.b, &mut self.r);
+  }
+}
+
+fn v_cycle(levels: &mut [Level]) {
+  let (fine, rest) = levels.split_first_mut().unwrap();
+  let coarse = match rest.first_mut() {
+    Some(coarse) => coarse,
+    None => {
+      fine.smooth(COARSEST_SWEEPS);
+      return;
+    }
+  };
+
+  fine.smooth(PRE_SMOOTH);
+  fine.compute_residual();
+
+  let (fnx, fny) = (fine.a.nx, fine.a.ny);
+  let (cnx, cny) = (coarse.a.nx, coarse.a.ny);
+  let (wrap_x, wrap_y) = (coarse.a.wrap_x, coarse.a.wrap_y);
+  coarse.b.iter_mut().for_each(|e| *e = 0f64);
+  coarse.x.iter_mut().for_each(|e| *e = 0f64);
+  for j in 0..fny {
+    for i in 0..fnx {
+      coarse.b[i / 2 + (j / 2) * cnx] += fine.r[i + j * fnx];
+    }
+  }
+  if coarse.a.singular() {
+    coarse.a.remove_mean(&mut coarse.b);
+  }
+
+  v_cycle(rest);
+  let cx = &rest[0].x;
+
+  // the coarse cell on the other side of fine cell `f` from its parent `c`
+  let beside = |f: usize, c: usize, n: usize, wrap: bool| match (f.is_multiple_of(2), c) {
+    (true, 0) if wrap => n - 1,
+    (true, _) => c.saturating_sub(1),
+    (false, _) if c + 1 == n && wrap => 0,
+    (false, _) => (c + 1).min(n - 1),
+  };
+
+  // bilinear interpolation of the cell-centred correction
+  for j in 0..fny {
+    let cj = j / 2;
+    let nj = beside(j, cj, cny, wrap_y);
+    for i in 0..fnx {
+      let ci = i / 2;
+      let ni = beside(i, ci, cnx, wrap_x);
+      let at = |x: usize, y: usize| cx[x + y * cnx];
+      fine.x[i + j * fnx] += 0.5625 * at(ci, cj)
+        + 0.1875 * (at(ni, cj) + at(ci, nj))
+        + 0.0625 * at(ni, nj);
+    }
+  }
+
+  fine.smooth(POST_SMOOTH);
+}
+
+/// Solves the pressure Poisson equation of `Fluid::project`
+/// (`4p - sum of neighbours = div`) with V-cycles
+/// until the relative residual drops below `cfg.tolerance`. Each V-cycle
+/// counts as one iteration.
+pub fn solve(g: Grid, obs: &Obstacles, p: &mut [f64], div: &[f64], cfg: SolverConfig) -> SolveStats {
+  let mut levels = vec![Level::new(Laplacian::for_grid(g, obs))];
+  while let Some(coarse) = levels.last().filter(|l| l.a.nx > 2 && l.a.ny > 2).map(Level::coarsen) {
+    levels.push(coarse);
+  }
+
+  let fine = &mut levels[0];
+  fine.a.gather_rhs(g, div, &mut fine.b);
+  fine.a.gather(g, p, &mut fine.x);
+  if fine.a.singular() {
+    fine.a.remove_mean(&mut fine.b);
+  }
+  let b_norm = norm(&fine.b);
+  let relative = |r: f64| if b_norm > 0f64 { r / b_norm } else { r };
+
+  fine.compute_residual();
+  let mut stats = SolveStats {
+    iterations: 0,
+    residual: relative(norm(&fine.r)),
+  };
+  while stats.iterations < cfg.max_iterations && stats.residual > cfg.tolerance {
+    v_cycle(&mut levels);
+    levels[0].compute_residual();
+    stats.iterations += 1;
+    stats.residual = relative(norm(&levels[0].r));
+  }
+
+  let fine = &levels[0];
+  fine.a.scatter(g, &fine.x, p);
+  stats
+}
@@ -0,0 +1,258 @@
+use crate::boundary::Field;
+use crate::fluid::Grid;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+  Rect { width: f64, height: f64 },
+  Circle { radius: f64 },
+}
+
+/// A rigid obstacle that can move through the fluid. Positions and sizes are
+/// in cells, velocities in the same units as `Fluid::add_velocity` and the
+/// angular velocity in radians per second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+  pub shape: Shape,
+  pub x: f64,
+  pub y: f64,
+  pub angle: f64,
+  pub vx: f64,
+  pub vy: f64,
+  pub angular_velocity: f64,
+  /// Where `Fluid::move_body_to` is steering the body, if anywhere.
+  pub target: Option<(f64, f64)>,
+}
+
+impl Body {
+  pub fn new(shape: Shape, x: f64, y: f64, angle: f64) -> Body {
+    Body {
+      shape,
+      x,
+      y,
+      angle,
+      vx: 0f64,
+      vy: 0f64,
+      angular_velocity: 0f64,
+      target: None,
+    }
+  }
+
+  fn contains(&self, x: f64, y: f64) -> bool {
+    let (dx, dy) = (x - self.x, y - self.y);
+    match self.shape {
+      Shape::Circle { radius } => dx * dx + dy * dy <= radius * radius,
+      Shape::Rect { width, height } => {
+        let (sin, cos) = self.angle.sin_cos();
+        let u = dx * cos + dy * sin;
+        let v = -dx * sin + dy * cos;
+        u.abs() <= width / 2f64 && v.abs() <= height / 2f64
+      }
+    }
+  }
+
+  fn extent(&self) -> f64 {
+    match self.shape {
+      Shape::Circle { radius } => radius,
+      Shape::Rect { width, height } => (width * width + height * height).sqrt() / 2f64,
+    }
+  }
+}
+
+/// Solid cells inside the domain: cells painted solid, which never move, and
+/// cells covered by a `Body`. Only interior cells can be solid; the ghost
+/// border is handled by `Boundaries`.
+#[derive(Clone, Debug)]
+pub struct Obstacles {
+  painted: Vec<bool>,
+  owner: Vec<Option<u32>>,
+  solid: Vec<bool>,
+  vx: Vec<f64>,
+  vy: Vec<f64>,
+  bodies: Vec<Option<Body>>,
+  count: usize,
+}
+
+impl Obstacles {
+  pub fn new(g: Grid) -> Obstacles {
+    let cells = g.cells();
+    Obstacles {
+      painted: vec![false; cells],
+      owner: vec![None; cells],
+      solid: vec![false; cells],
+      vx: vec![0f64; cells],
+      vy: vec![0f64; cells],
+      bodies: Vec::new(),
+      count: 0,
+    }
+  }
+
+  pub fn any(&self) -> bool {
+    self.count > 0
+  }
+
+  pub fn is_solid(&self, k: usize) -> bool {
+    self.solid[k]
+  }
+
+  pub fn mask(&self) -> &[bool] {
+    &self.solid
+  }
+
+  /// The body covering cell `k`, if any.
+  pub fn owner(&self, k: usize) -> Option<u32> {
+    self.owner[k]
+  }
+
+  /// Velocity of the obstacle surface in solid cell `k`.
+  pub fn velocity(&self, k: usize) -> (f64, f64) {
+    (self.vx[k], self.vy[k])
+  }
+
+  /// Ids of the bodies that have not been removed.
+  pub fn body_ids(&self) -> impl Iterator<Item = u32> + '_ {
+    self.bodies.iter().enumerate().filter(|(_, b)| b.is_some()).map(|(id, _)| id as u32)
+  }
+
+  /// Paints interior cell `(x, y)` solid or fluid; cells outside the interior
+  /// are ignored, and a cell under a body stays solid. Returns the cell index
+  /// when the cell changed.
+  pub fn set(&mut self, g: Grid, x: i32, y: i32, solid: bool) -> Option<usize> {
+    if x < 1 || y < 1 || x > g.width - 2 || y > g.height - 2 {
+      return None;
+    }
+    let k = g.ix(x, y);
+    self.painted[k] = solid;
+    let was_solid = self.solid[k];
+    self.solid[k] = solid || self.owner[k].is_some();
+    match (was_solid, self.solid[k]) {
+      (false, true) => self.count += 1,
+      (true, false) => self.count -= 1,
+      _ => return None,
+    }
+    Some(k)
+  }
+
+  /// Removes every painted cell and every body.
+  pub fn clear(&mut self, g: Grid) {
+    self.painted.iter_mut().for_each(|s| *s = false);
+    self.bodies.clear();
+    self.rasterize(g);
+  }
+
+  pub fn add_body(&mut self, g: Grid, body: Body) -> u32 {
+    self.bodies.push(Some(body));
+    self.rasterize(g);
+    (self.bodies.len() - 1) as u32
+  }
+
+  pub fn remove_body(&mut self, g: Grid, id: u32) {
+    if let Some(body) = self.bodies.get_mut(id as usize) {
+      *body = None;
+      self.rasterize(g);
+    }
+  }
+
+  pub fn body_mut(&mut self, id: u32) -> Option<&mut Body> {
+    self.bodies.get_mut(id as usize).and_then(Option::as_mut)
+  }
+
+  /// Gives every body with a target the velocity that reaches it after a
+  /// step of `dt`.
+  pub fn aim(&mut self, g: Grid, dt: f64) {
+    let travel = dt * g.scale();
+    for body in self.bodies.iter_mut().flatten() {
+      if let Some((x, y)) = body.target {
+        body.vx = (x - body.x) / travel;
+        body.vy = (y - body.y) / travel;
+      }
+    }
+  }
+
+  /// Moves every body along its velocity for one step of `dt` and returns
+  /// the cells that have just been covered.
+  pub fn update(&mut self, g: Grid, dt: f64) -> Vec<usize> {
+    if self.bodies.iter().all(Option::is_none) {
+      return Vec::new();
+    }
+    for body in self.bodies.iter_mut().flatten() {
+      body.x += body.vx * dt * g.scale();
+      body.y += body.vy * dt * g.scale();
+      body.angle += body.angular_velocity * dt;
+    }
+    let was_solid = self.solid.clone();
+    self.rasterize(g);
+    (0..self.solid.len()).filter(|&k| self.solid[k] && !was_solid[k]).collect()
+  }
+
+  /// Rebuilds the solid mask and the velocity of every solid cell from the
+  /// painted cells and the current position of the bodies.
+  fn rasterize(&mut self, g: Grid) {
+    self.owner.iter_mut().for_each(|o| *o = None);
+    self.vx.iter_mut().for_each(|v| *v = 0f64);
+    self.vy.iter_mut().for_each(|v| *v = 0f64);
+    for (id, body) in self.bodies.iter().enumerate() {
+      let body = match body {
+        Some(body) => body,
+        None => continue,
+      };
+      let r = body.extent();
+      let (x0, x1) = (((body.x - r).floor() as i32).max(1), ((body.x + r).ceil() as i32).min(g.width - 2));
+      let (y0, y1) = (((body.y - r).floor() as i32).max(1), ((body.y + r).ceil() as i32).min(g.height - 2));
+      for y in y0..=y1 {
+        for x in x0..=x1 {
+          let (fx, fy) = (x as f64, y as f64);
+          if body.contains(fx, fy) {
+            let k = g.ix(x, y);
+            self.owner[k] = Some(id as u32);
+            // rigid motion: translation plus rotation about the body centre
+            self.vx[k] = body.vx - body.angular_velocity * (fy - body.y) / g.scale();
+            self.vy[k] = body.vy + body.angular_velocity * (fx - body.x) / g.scale();
+          }
+        }
+      }
+    }
+    for k in 0..self.solid.len() {
+      self.solid[k] = self.painted[k] || self.owner[k].is_some();
+    }
+    self.count = self.solid.iter().filter(|&&s| s).count();
+  }
+
+  /// Fills solid cells of `x` so the kernels see the right values next to an
+  /// obstacle: velocities take the velocity of the obstacle, so a moving body
+  /// drags and pushes the fluid around it, while scalars and pressure copy
+  /// the mean of the fluid around them so nothing is exchanged across the
+  /// obstacle surface.
+  pub fn set_bnd(&self, g: Grid, field: Field, x: &mut [f64]) {
+    if !self.any() {
+      return;
+    }
+    for j in 1..(g.height - 1) {
+      for i in 1..(g.width - 1) {
+        let k = g.ix(i, j);
+        if !self.solid[k] {
+          continue;
+        }
+        x[k] = match field {
+          Field::VelocityX => self.vx[k],
+          Field::VelocityY => self.vy[k],
+          Field::Scalar | Field::Pressure => {
+            let mut sum = 0f64;
+            let mut n = 0;
+            for (ni, nj) in [(i + 1, j), (i - 1, j), (i, j + 1), (i, j - 1)] {
+              let kk = g.ix(ni, nj);
+              if !self.solid[kk] {
+                sum += x[kk];
+                n += 1;
+              }
+            }
+            if n > 0 {
+              sum / n as f64
+            } else {
+              0f64
+            }
+          }
+        };
+      }
+    }
+  }
+}
@@ -0,0 +1,227 @@
+use crate::boundary::{Boundary, Side};
+use crate::fluid::Grid;
+use crate::obstacle::Obstacles;
+
+/// The pressure Poisson operator `diag * x - sum of weighted neighbours` over
+/// the interior cells of a grid, stored without the ghost border. Cells are
+/// coupled to their east and north neighbours by edge weights; a wall is an
+/// edge of weight zero. The east edges of the last column and the north edges
+/// of the last row lead nowhere unless that axis wraps around. `fixed` adds to
+/// the diagonal of cells next to an edge where the pressure is held at zero.
+pub struct Laplacian {
+  pub nx: usize,
+  pub ny: usize,
+  pub wrap_x: bool,
+  pub wrap_y: bool,
+  pub east: Vec<f64>,
+  pub north: Vec<f64>,
+  pub fixed: Vec<f64>,
+  pub diag: Vec<f64>,
+}
+
+impl Laplacian {
+  pub fn new(
+    nx: usize,
+    ny: usize,
+    (wrap_x, wrap_y): (bool, bool),
+    east: Vec<f64>,
+    north: Vec<f64>,
+    fixed: Vec<f64>,
+  ) -> Laplacian {
+    let mut a = Laplacian {
+      nx,
+      ny,
+      wrap_x,
+      wrap_y,
+      east,
+      north,
+      diag: fixed.clone(),
+      fixed,
+    };
+    for j in 0..ny {
+      for i in 0..nx {
+        let k = i + j * nx;
+        let mut diag = 0f64;
+        a.for_each_neighbour(k, i, j, |_, w| diag += w);
+        a.diag[k] += diag;
+      }
+    }
+    a
+  }
+
+  /// The interior of `g` with the pressure conditions `set_bnd` imposes on
+  /// its edges: zero gradient at walls and inflows, zero pressure at outflows,
+  /// wrapping for periodic axes. Solid cells are cut out of the operator,
+  /// leaving zero-gradient faces around them.
+  pub fn for_grid(g: Grid, obs: &Obstacles) -> Laplacian {
+    let nx = (g.width - 2) as usize;
+    let ny = (g.height - 2) as usize;
+    let e = g.boundaries;
+    let (wrap_x, wrap_y) = (e.periodic_x(), e.periodic_y());
+    let mut east = vec![1f64; nx * ny];
+    let mut north = vec![1f64; nx * ny];
+    let mut fixed = vec![0f64; nx * ny];
+    // a ghost cell mirroring minus the pressure next to it adds 2 to the
+    // diagonal, one for the edge and one for the mirrored value
+    let open = |side: Side| if e.get(side).kind == Boundary::Outflow { 2f64 } else { 0f64 };
+    for j in 0..ny {
+      if !wrap_x {
+        east[nx - 1 + j * nx] = 0f64;
+      }
+      fixed[j * nx] += open(Side::Left);
+      fixed[nx - 1 + j * nx] += open(Side::Right);
+    }
+    for i in 0..nx {
+      if !wrap_y {
+        north[i + (ny - 1) * nx] = 0f64;
+      }
+      fixed[i] += open(Side::Top);
+      fixed[i + (ny - 1) * nx] += open(Side::Bottom);
+    }
+    if obs.any() {
+      let solid = |i: usize, j: usize| obs.is_solid(g.ix(i as i32 + 1, j as i32 + 1));
+      for j in 0..ny {
+        for i in 0..nx {
+          let k = i + j * nx;
+          if solid(i, j) {
+            east[k] = 0f64;
+            north[k] = 0f64;
+            fixed[k] = 0f64;
+            east[if i > 0 { k - 1 } else { k + nx - 1 }] = 0f64;
+            north[if j > 0 { k - nx } else { k + (ny - 1) * nx }] = 0f64;
+          }
+        }
+      }
+    }
+    Laplacian::new(nx, ny, (wrap_x, wrap_y), east, north, fixed)
+  }
+
+  /// Without any fixed pressure the operator only determines the pressure up
+  /// to a constant.
+  pub fn singular(&self) -> bool {
+    self.fixed.iter().all(|&f| f == 0f64)
+  }
+
+  /// Removes the mean over the cells that take part in the operator.
+  pub fn remove_mean(&self, v: &mut [f64]) {
+    let active = || v.iter().zip(&self.diag).filter(|(_, &d)| d > 0f64);
+    let n = active().count();
+    if n == 0 {
+      return;
+    }
+    let mean = active().map(|(e, _)| e).sum::<f64>() / n as f64;
+    for (e, &d) in v.iter_mut().zip(&self.diag) {
+      *e = if d > 0f64 { *e - mean } else { 0f64 };
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.nx * self.ny
+  }
+
+  /// Calls `f(index, edge weight)` for each neighbour of cell
+  /// `k = i + j * nx`.
+  fn for_each_neighbour<F: FnMut(usize, f64)>(&self, k: usize, i: usize, j: usize, mut f: F) {
+    let (nx, ny) = (self.nx, self.ny);
+    if i + 1 < nx {
+      f(k + 1, self.east[k]);
+    } else if self.wrap_x {
+      f(k + 1 - nx, self.east[k]);
+    }
+    if j + 1 < ny {
+      f(k + nx, self.north[k]);
+    } else if self.wrap_y {
+      f(i, self.north[k]);
+    }
+    if i > 0 {
+      f(k - 1, self.east[k - 1]);
+    } else if self.wrap_x {
+      f(k + nx - 1, self.east[k + nx - 1]);
+    }
+    if j > 0 {
+      f(k - nx, self.north[k - nx]);
+    } else if self.wrap_y {
+      f(k + (ny - 1) * nx, self.north[k + (ny - 1) * nx]);
+    }
+  }
+
+  /// Weighted sum of the neighbours of cell `k = i + j * nx`.
+  pub fn neighbours(&self, x: &[f64], k: usize, i: usize, j: usize) -> f64 {
+    let mut sum = 0f64;
+    self.for_each_neighbour(k, i, j, |n, w| sum += w * x[n]);
+    sum
+  }
+
+  /// Gauss-Seidel sweeps on `A x = b`, skipping cells outside the operator.
+  pub fn smooth(&self, x: &mut [f64], b: &[f64], sweeps: usize) {
+    for _ in 0..sweeps {
+      for j in 0..self.ny {
+        for i in 0..self.nx {
+          let k = i + j * self.nx;
+          if self.diag[k] > 0f64 {
+            x[k] = (b[k] + self.neighbours(x, k, i, j)) / self.diag[k];
+          }
+        }
+      }
+    }
+  }
+
+  pub fn apply(&self, x: &[f64], out: &mut [f64]) {
+    for j in 0..self.ny {
+      for i in 0..self.nx {
+        let k = i + j * self.nx;
+        out[k] = self.diag[k] * x[k] - self.neighbours(x, k, i, j);
+      }
+    }
+  }
+
+  /// `out = b - A x`.
+  pub fn residual(&self, x: &[f64], b: &[f64], out: &mut [f64]) {
+    self.apply(x, out);
+    out.iter_mut().zip(b).for_each(|(r, b)| *r = b - *r);
+  }
+
+  /// Copies the interior of a ghosted grid buffer into operator layout.
+  pub fn gather(&self, g: Grid, from: &[f64], to: &mut [f64]) {
+    for j in 0..self.ny {
+      for i in 0..self.nx {
+        to[i + j * self.nx] = from[g.ix(i as i32 + 1, j as i32 + 1)];
+      }
+    }
+  }
+
+  /// Like `gather`, but leaves out cells that are not part of the operator
+  /// so a right-hand side stays consistent with it.
+  pub fn gather_rhs(&self, g: Grid, from: &[f64], to: &mut [f64]) {
+    self.gather(g, from, to);
+    for (b, &d) in to.iter_mut().zip(&self.diag) {
+      if d == 0f64 {
+        *b = 0f64;
+      }
+    }
+  }
+
+  /// Writes an operator-layout vector back into the interior of a ghosted
+  /// grid buffer.
+  pub fn scatter(&self, g: Grid, from: &[f64], to: &mut [f64]) {
+    for j in 0..self.ny {
+      for i in 0..self.nx {
+        to[g.ix(i as i32 + 1, j as i32 + 1)] = from[i + j * self.nx];
+      }
+    }
+  }
+}
+
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+  a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+pub fn norm(v: &[f64]) -> f64 {
+  dot(v, v).sqrt()
+}
+
+/// A singular (pure Neumann) problem is only solvable for its zero-mean part.
+pub fn remove_mean(v: &mut [f64]) {
+  let mean = v.iter().sum::<f64>() / v.len() as f64;
+  v.iter_mut().for_each(|e| *e -= mean);
+}
@@ -0,0 +1,52 @@
+/// A passive quantity carried by the flow, such as one colour channel of dye
+/// or a tracer concentration. It never pushes back on the velocity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scalar {
+  pub name: String,
+  pub diffusion: f64,
+  /// Exponential decay rate per second.
+  pub decay: f64,
+  pub values: Vec<f64>,
+}
+
+/// The named scalars of a `Fluid`, in the order they were added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scalars {
+  fields: Vec<Scalar>,
+}
+
+impl Scalars {
+  /// Adds an empty field called `name`, or changes the diffusion rate of the
+  /// field already called that.
+  pub fn insert(&mut self, name: &str, diffusion: f64, cells: usize) {
+    match self.get_mut(name) {
+      Some(field) => field.diffusion = diffusion,
+      None => self.fields.push(Scalar {
+        name: name.to_string(),
+        diffusion,
+        decay: 0f64,
+        values: vec![0f64; cells],
+      }),
+    }
+  }
+
+  pub fn remove(&mut self, name: &str) {
+    self.fields.retain(|f| f.name != name);
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Scalar> {
+    self.fields.iter().find(|f| f.name == name)
+  }
+
+  pub fn get_mut(&mut self, name: &str) -> Option<&mut Scalar> {
+    self.fields.iter_mut().find(|f| f.name == name)
+  }
+
+  pub fn names(&self) -> Vec<String> {
+    self.fields.iter().map(|f| f.name.clone()).collect()
+  }
+
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Scalar> {
+    self.fields.iter_mut()
+  }
+}
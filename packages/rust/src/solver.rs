@@ -0,0 +1,75 @@
+use crate::error::FluidError;
+use crate::fluid::check;
+use wasm_bindgen::prelude::*;
+
+/// Which algorithm `Fluid::project` uses for the pressure Poisson equation.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PressureSolver {
+  /// Gauss-Seidel sweeps, cheap per iteration but slow to converge.
+  #[default]
+  GaussSeidel,
+  /// Geometric multigrid V-cycles.
+  Multigrid,
+  /// Conjugate gradients with an incomplete-Cholesky preconditioner; slower,
+  /// but converges tightly for validation runs.
+  ConjugateGradient,
+  /// Exact FFT solve, leaving the velocity exactly divergence-free. Needs a
+  /// periodic domain; with walls it falls back to multigrid.
+  Spectral,
+}
+
+/// Stopping criteria for the iterative linear solves used by `diffuse` and
+/// `project`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SolverConfig {
+  /// Upper bound on the number of sweeps per solve.
+  pub max_iterations: u32,
+  /// Relative residual at which a solve stops early.
+  pub tolerance: f64,
+}
+
+#[wasm_bindgen]
+impl SolverConfig {
+  #[wasm_bindgen(constructor)]
+  pub fn new(max_iterations: u32, tolerance: f64) -> SolverConfig {
+    SolverConfig {
+      max_iterations,
+      tolerance,
+    }
+  }
+}
+
+impl SolverConfig {
+  /// Rejects configs under which a solve would stop before its first sweep
+  /// or never meet its tolerance test.
+  pub(crate) fn checked(self) -> Result<SolverConfig, FluidError> {
+    check("max_iterations", self.max_iterations as f64, false)?;
+    check("tolerance", self.tolerance, true)?;
+    Ok(self)
+  }
+}
+
+impl Default for SolverConfig {
+  fn default() -> SolverConfig {
+    SolverConfig::new(20, 1e-4)
+  }
+}
+
+/// What the pressure solve did during the last `Fluid::step`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SolveStats {
+  /// Sweeps spent across every pressure solve of the step.
+  pub iterations: u32,
+  /// Relative residual left by the final pressure solve.
+  pub residual: f64,
+}
+
+impl SolveStats {
+  pub(crate) fn accumulate(&mut self, other: SolveStats) {
+    self.iterations += other.iterations;
+    self.residual = other.residual;
+  }
+}
@@ -0,0 +1,91 @@
+use crate::fluid::Grid;
+use crate::poisson::{norm, remove_mean};
+use crate::solver::SolveStats;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f64::consts::PI;
+
+/// Runs the 2D FFT of an `nx` by `ny` row-major buffer in place, one axis at
+/// a time.
+fn fft_2d(planner: &mut FftPlanner<f64>, data: &mut [Complex<f64>], nx: usize, ny: usize, inverse: bool) {
+  let (row, column) = if inverse {
+    (planner.plan_fft_inverse(nx), planner.plan_fft_inverse(ny))
+  } else {
+    (planner.plan_fft_forward(nx), planner.plan_fft_forward(ny))
+  };
+  row.process(data);
+
+  let mut transposed = vec![Complex::default(); nx * ny];
+  for j in 0..ny {
+    for i in 0..nx {
+      transposed[j + i * ny] = data[i + j * nx];
+    }
+  }
+  column.process(&mut transposed);
+  for j in 0..ny {
+    for i in 0..nx {
+      data[i + j * nx] = transposed[j + i * ny];
+    }
+  }
+}
+
+/// Solves the pressure equation of `Fluid::project` on a periodic domain in
+/// one shot with an FFT.
+///
+/// `project` measures divergence and applies the pressure gradient with
+/// central differences, which together act as a Laplacian reaching two cells
+/// out rather than the compact 5-point one. Dividing by that operator's
+/// symbol, `sin²(kx) + sin²(ky)`, instead of the compact one leaves the
+/// projected velocity with exactly zero discrete divergence. The residual
+/// reported is that of the wide system.
+pub fn solve(g: Grid, p: &mut [f64], div: &[f64]) -> SolveStats {
+  let nx = (g.width - 2) as usize;
+  let ny = (g.height - 2) as usize;
+  let at = |i: usize, j: usize| g.ix(i as i32 + 1, j as i32 + 1);
+
+  let mut b = vec![0f64; nx * ny];
+  for j in 0..ny {
+    for i in 0..nx {
+      b[i + j * nx] = div[at(i, j)];
+    }
+  }
+  remove_mean(&mut b);
+
+  let mut data: Vec<Complex<f64>> = b.iter().map(|&v| Complex::new(v, 0f64)).collect();
+  let mut planner = FftPlanner::new();
+  fft_2d(&mut planner, &mut data, nx, ny, false);
+  for ky in 0..ny {
+    let sy = (PI * ky as f64 / ny as f64 * 2f64).sin();
+    for kx in 0..nx {
+      let sx = (PI * kx as f64 / nx as f64 * 2f64).sin();
+      let symbol = sx * sx + sy * sy;
+      let k = kx + ky * nx;
+      data[k] = if symbol > 1e-12 { data[k] / symbol } else { Complex::default() };
+    }
+  }
+  fft_2d(&mut planner, &mut data, nx, ny, true);
+
+  let scale = 1f64 / (nx * ny) as f64;
+  let x: Vec<f64> = data.iter().map(|c| c.re * scale).collect();
+  for j in 0..ny {
+    for i in 0..nx {
+      p[at(i, j)] = x[i + j * nx];
+    }
+  }
+
+  let mut r = vec![0f64; nx * ny];
+  for j in 0..ny {
+    for i in 0..nx {
+      // two cells back is `n - 2` ahead, which also holds for axes of one or
+      // two cells once the 2 is reduced modulo `n`
+      let far = |di: usize, dj: usize| x[(i + di) % nx + ((j + dj) % ny) * nx];
+      let wide = far(2, 0) + far(nx - 2 % nx, 0) + far(0, 2) + far(0, ny - 2 % ny);
+      r[i + j * nx] = b[i + j * nx] - (4f64 * x[i + j * nx] - wide) / 4f64;
+    }
+  }
+  let b_norm = norm(&b);
+  SolveStats {
+    iterations: 1,
+    residual: if b_norm > 0f64 { norm(&r) / b_norm } else { norm(&r) },
+  }
+}
@@ -0,0 +1,693 @@
+use vite_wasm_functions::{
+  sample, Advection, Backtrace, Boundary, Fluid, FluidError, Grid, Interpolation, MacFluid, PressureSolver, Side,
+  SolverConfig,
+};
+
+const TOLERANCE: f64 = 1e-5;
+
+/// A fluid whose velocity field is smooth but far from divergence-free.
+fn swirling_fluid(width: i32, height: i32, pressure_solver: PressureSolver) -> Fluid {
+  let mut fluid = Fluid::new(width, height, 0.05, 0.0, 0.0).unwrap();
+  fluid.set_solver(SolverConfig::new(100_000, TOLERANCE)).unwrap();
+  fluid.set_pressure_solver(pressure_solver);
+  for j in 0..height {
+    for i in 0..width {
+      let (x, y) = (i as f64 * 0.37, j as f64 * 0.23);
+      fluid.add_velocity(i, j, (x * y).sin(), (x + 2.0 * y).cos());
+    }
+  }
+  fluid
+}
+
+#[test]
+fn non_square_grids_index_rows_and_share_one_scale() {
+  let g = Grid::new(6, 4);
+  assert_eq!(g.cells(), 24);
+  assert_eq!(g.ix(1, 2), 13);
+  assert_eq!(g.ix(5, 3), 23);
+  assert_eq!(g.ix(9, -1), 5);
+
+  // the longer side spans one unit, so one step at unit speed moves dye
+  // dt * 20 = 1 cell along either axis
+  for (width, height, vx, vy) in [(22, 12, 1.0, 0.0), (12, 22, 0.0, 1.0)] {
+    let mut fluid = Fluid::new(width, height, 0.05, 0.0, 0.0).unwrap();
+    fluid.set_boundary(Boundary::Periodic);
+    fluid.set_pressure_solver(PressureSolver::Spectral);
+    for j in 0..height {
+      for i in 0..width {
+        fluid.add_velocity(i, j, vx, vy);
+      }
+    }
+    fluid.add_density(5, 5, 1.0);
+    fluid.step();
+    let g = fluid.grid();
+    let moved = g.ix(5 + vx as i32, 5 + vy as i32);
+    assert!((fluid.density()[moved] - 1.0).abs() < 1e-9, "{}x{}", width, height);
+  }
+}
+
+#[test]
+fn gauss_seidel_stops_at_the_cap_or_the_tolerance() {
+  // a step projects twice, each solve capped at `max_iterations` sweeps
+  let mut capped = swirling_fluid(24, 16, PressureSolver::GaussSeidel);
+  capped.set_solver(SolverConfig::new(3, 0.0)).unwrap();
+  capped.step();
+  let stats = capped.solve_stats();
+  assert_eq!(stats.iterations, 6);
+  assert!(stats.residual > 0.0);
+
+  let mut converged = swirling_fluid(24, 16, PressureSolver::GaussSeidel);
+  converged.step();
+  let stats = converged.solve_stats();
+  assert!(stats.residual <= TOLERANCE, "{:?}", stats);
+  assert!(stats.iterations < 2 * 100_000, "{:?}", stats);
+}
+
+#[test]
+fn multigrid_projects_in_far_fewer_iterations() {
+  let mut gauss_seidel = swirling_fluid(48, 40, PressureSolver::GaussSeidel);
+  let mut multigrid = swirling_fluid(48, 40, PressureSolver::Multigrid);
+  gauss_seidel.step();
+  multigrid.step();
+
+  let gs = gauss_seidel.solve_stats();
+  let mg = multigrid.solve_stats();
+  assert!(gs.residual <= TOLERANCE, "{:?}", gs);
+  assert!(mg.residual <= TOLERANCE, "{:?}", mg);
+  assert!(mg.iterations * 100 < gs.iterations, "{:?} vs {:?}", mg, gs);
+}
+
+#[test]
+fn conjugate_gradient_reaches_tolerance() {
+  let mut fluid = swirling_fluid(48, 40, PressureSolver::ConjugateGradient);
+  fluid.step();
+
+  let stats = fluid.solve_stats();
+  assert!(stats.residual <= TOLERANCE, "{:?}", stats);
+  assert!(stats.iterations < 200, "{:?}", stats);
+}
+
+#[test]
+fn spectral_projection_is_exact_on_periodic_domain() {
+  let mut fluid = swirling_fluid(48, 40, PressureSolver::Spectral);
+  fluid.set_boundary(Boundary::Periodic);
+  fluid.step();
+
+  let stats = fluid.solve_stats();
+  assert_eq!(stats.iterations, 2);
+  assert!(stats.residual < 1e-12, "{:?}", stats);
+
+  // axes only one or two cells thick wrap onto themselves
+  for (width, height) in [(3, 10), (10, 3), (4, 4)] {
+    let mut thin = swirling_fluid(width, height, PressureSolver::Spectral);
+    thin.set_boundary(Boundary::Periodic);
+    thin.step();
+    assert!(thin.solve_stats().residual.is_finite());
+  }
+}
+
+#[test]
+fn inflow_carries_dye_to_the_outflow() {
+  let mut fluid = Fluid::new(40, 20, 0.02, 0.0, 0.0001).unwrap();
+  fluid.set_pressure_solver(PressureSolver::Multigrid);
+  fluid.set_edge(Side::Top, Boundary::NoSlip);
+  fluid.set_edge(Side::Bottom, Boundary::NoSlip);
+  fluid.set_inflow(Side::Left, 0.5, 0.0);
+  fluid.set_edge(Side::Right, Boundary::Outflow);
+  for _ in 0..150 {
+    fluid.add_density(2, 10, 10.0);
+    fluid.step();
+  }
+
+  let g = fluid.grid();
+  let density = fluid.density();
+  assert!(density[g.ix(35, 10)] > 1.0, "{}", density[g.ix(35, 10)]);
+}
+
+#[test]
+fn dye_flows_around_obstacles() {
+  let mut fluid = Fluid::new(40, 20, 0.02, 0.0, 0.0001).unwrap();
+  fluid.set_pressure_solver(PressureSolver::ConjugateGradient);
+  fluid.set_inflow(Side::Left, 0.5, 0.0);
+  fluid.set_edge(Side::Right, Boundary::Outflow);
+  fluid.set_obstacle_circle(12.0, 10.0, 3.0, true);
+  for _ in 0..100 {
+    for y in 3..17 {
+      fluid.add_density(2, y, 5.0);
+    }
+    fluid.step();
+  }
+
+  let g = fluid.grid();
+  let density = fluid.density();
+  assert!(fluid.is_obstacle(12, 10));
+  assert_eq!(density[g.ix(12, 10)], 0.0);
+  assert!(density[g.ix(17, 5)] > density[g.ix(17, 10)]);
+}
+
+#[test]
+fn walled_in_cells_stay_finite() {
+  for solver in [PressureSolver::GaussSeidel, PressureSolver::Multigrid, PressureSolver::ConjugateGradient] {
+    let mut fluid = Fluid::new(20, 20, 0.05, 0.0001, 0.0).unwrap();
+    fluid.set_pressure_solver(solver);
+    for (x, y) in [(9, 10), (11, 10), (10, 9), (10, 11)] {
+      fluid.set_obstacle(x, y, true);
+    }
+    for _ in 0..6 {
+      fluid.add_density(5, 5, 10.0);
+      fluid.add_velocity(5, 5, 1.0, 1.0);
+      fluid.step();
+    }
+    let finite = |v: Vec<f64>| v.iter().all(|x| x.is_finite());
+    assert!(finite(fluid.velocity_x()) && finite(fluid.velocity_y()), "{:?}", solver);
+    assert!(finite(fluid.density()) && finite(fluid.pressure()), "{:?}", solver);
+  }
+}
+
+#[test]
+fn moving_body_pushes_the_fluid() {
+  let mut fluid = Fluid::new(40, 20, 0.02, 0.0, 0.0).unwrap();
+  fluid.set_pressure_solver(PressureSolver::Multigrid);
+  let paddle = fluid.add_rect_body(10.0, 10.0, 2.0, 8.0, 0.0);
+  fluid.set_body_velocity(paddle, 0.5, 0.0, 0.0);
+  for _ in 0..20 {
+    fluid.step();
+  }
+
+  let g = fluid.grid();
+  let (vx, _) = fluid.velocity();
+  assert!(fluid.is_obstacle(17, 10));
+  assert!(!fluid.is_obstacle(10, 10));
+  assert!(vx[g.ix(22, 10)] > 0.1);
+  assert!(vx[g.ix(17, 3)] < 0.0);
+}
+
+#[test]
+fn steered_body_stops_at_its_target() {
+  let mut fluid = Fluid::new(40, 20, 0.05, 0.0, 0.0).unwrap();
+  let puck = fluid.add_circle_body(10.0, 10.0, 1.5);
+  fluid.move_body_to(puck, 30.0, 10.0);
+  // several steps in one call arrive once rather than overshooting
+  fluid.advance(0.16);
+  assert_eq!(fluid.steps_taken(), 3);
+  assert!(fluid.is_obstacle(30, 10));
+  assert!(!fluid.is_obstacle(10, 10) && !fluid.is_obstacle(34, 10));
+}
+
+#[test]
+fn stream_drags_a_cylinder_downstream() {
+  let mut fluid = Fluid::new(48, 24, 0.02, 0.0, 0.001).unwrap();
+  fluid.set_pressure_solver(PressureSolver::ConjugateGradient);
+  fluid.set_inflow(Side::Left, 0.5, 0.0);
+  fluid.set_edge(Side::Right, Boundary::Outflow);
+  let cylinder = fluid.add_circle_body(16.0, 11.5, 3.0);
+  for _ in 0..60 {
+    fluid.step();
+  }
+
+  let drag = fluid.drag_history(cylinder);
+  let force = fluid.force(cylinder);
+  assert_eq!(drag.len(), 60);
+  assert_eq!(drag[59], force.drag);
+  assert!(force.drag > 0.0);
+  assert!(force.lift.abs() < 0.1 * force.drag);
+
+  fluid.remove_body(cylinder);
+  assert!(fluid.drag_history(cylinder).is_empty());
+  assert!(fluid.lift_history(cylinder).is_empty());
+  assert_eq!(fluid.force(cylinder).drag, 0.0);
+}
+
+/// Sum of the squared vorticity over the interior, in grid units.
+fn enstrophy(fluid: &Fluid) -> f64 {
+  let g = fluid.grid();
+  let (vx, vy) = fluid.velocity();
+  let mut sum = 0.0;
+  for j in 1..g.height - 1 {
+    for i in 1..g.width - 1 {
+      let curl = (vy[g.ix(i + 1, j)] - vy[g.ix(i - 1, j)]) - (vx[g.ix(i, j + 1)] - vx[g.ix(i, j - 1)]);
+      sum += curl * curl;
+    }
+  }
+  sum
+}
+
+#[test]
+fn vorticity_confinement_keeps_swirls() {
+  let run = |strength: f64| {
+    let mut fluid = Fluid::new(48, 48, 0.05, 0.0, 0.0).unwrap();
+    fluid.set_pressure_solver(PressureSolver::Multigrid);
+    fluid.set_vorticity(strength);
+    for j in 0..48 {
+      for i in 0..48 {
+        let (x, y) = (i as f64 * 0.5, j as f64 * 0.5);
+        fluid.add_velocity(i, j, 0.3 * y.sin() * x.cos(), -0.3 * x.sin() * y.cos());
+      }
+    }
+    for _ in 0..40 {
+      fluid.step();
+    }
+    enstrophy(&fluid)
+  };
+
+  let plain = run(0.0);
+  let confined = run(1.0);
+  assert!(confined > 1.5 * plain, "{} vs {}", confined, plain);
+}
+
+/// Mean row of the dye, weighted by density.
+fn dye_height(fluid: &Fluid) -> f64 {
+  let g = fluid.grid();
+  let density = fluid.density();
+  let (mut mass, mut moment) = (0.0, 0.0);
+  for j in 0..g.height {
+    for i in 0..g.width {
+      mass += density[g.ix(i, j)];
+      moment += density[g.ix(i, j)] * j as f64;
+    }
+  }
+  moment / mass
+}
+
+#[test]
+fn hot_smoke_rises_and_heavy_smoke_sinks() {
+  let run = |temperature: f64, weight: f64| {
+    let mut fluid = Fluid::new(32, 32, 0.02, 0.0, 0.0).unwrap();
+    fluid.set_pressure_solver(PressureSolver::Multigrid);
+    fluid.set_smoke_weight(weight);
+    for j in 14..18 {
+      for i in 14..18 {
+        fluid.add_density(i, j, 1.0);
+        fluid.add_temperature(i, j, temperature);
+      }
+    }
+    for _ in 0..30 {
+      fluid.step();
+    }
+    dye_height(&fluid)
+  };
+
+  assert!(run(5.0, 0.0) < 14.5);
+  assert!(run(0.0, 5.0) > 16.5);
+  assert!((run(0.0, 0.0) - 15.5).abs() < 1e-9);
+}
+
+#[test]
+fn scalar_fields_diffuse_at_their_own_rate() {
+  let mut fluid = Fluid::new(24, 24, 0.05, 0.0, 0.0).unwrap();
+  fluid.add_scalar_field("red", 0.0).unwrap();
+  fluid.add_scalar_field("blue", 0.002).unwrap();
+  assert!(fluid.add_scalar_field("green", f64::NAN).is_err());
+  assert!(fluid.add_scalar_field("blue", -1.0).is_err());
+  fluid.add_scalar("red", 12, 12, 10.0);
+  fluid.add_scalar("blue", 12, 12, 10.0);
+  fluid.add_scalar("green", 12, 12, 10.0);
+  for _ in 0..10 {
+    fluid.step();
+  }
+
+  let g = fluid.grid();
+  let red = fluid.scalar("red").unwrap();
+  let blue = fluid.scalar("blue").unwrap();
+  assert_eq!(fluid.scalar_names(), ["red", "blue"]);
+  assert!(fluid.scalar("green").is_none());
+  assert_eq!(red[g.ix(12, 12)], 10.0);
+  assert_eq!(red[g.ix(13, 12)], 0.0);
+  assert!(blue[g.ix(12, 12)] < 5.0);
+  assert!(blue[g.ix(13, 12)] > 0.1);
+}
+
+#[test]
+fn decay_fades_each_field_at_its_own_rate() {
+  let mut fluid = Fluid::new(16, 16, 0.1, 0.0, 0.0).unwrap();
+  fluid.add_scalar_field("tracer", 0.0).unwrap();
+  fluid.set_density_decay(1.0);
+  fluid.set_scalar_decay("tracer", 2.0);
+  fluid.add_density(8, 8, 1.0);
+  fluid.add_scalar("tracer", 8, 8, 1.0);
+  for _ in 0..10 {
+    fluid.step();
+  }
+
+  let k = fluid.grid().ix(8, 8);
+  assert!((fluid.density()[k] - (-1.0f64).exp()).abs() < 1e-12);
+  assert!((fluid.scalar("tracer").unwrap()[k] - (-2.0f64).exp()).abs() < 1e-12);
+}
+
+#[test]
+fn force_fields_accelerate_the_fluid() {
+  let mut fluid = Fluid::new(20, 10, 0.05, 0.0, 0.0).unwrap();
+  fluid.set_boundary(Boundary::Periodic);
+  fluid.set_pressure_solver(PressureSolver::Spectral);
+  fluid.set_uniform_force(1.0, 0.0);
+  let cells = fluid.grid().cells();
+  assert_eq!(
+    fluid.set_force_map(vec![0.0; cells], vec![0.0; 3]),
+    Err(FluidError::BufferLength { expected: cells, found: 3 })
+  );
+  fluid.step();
+
+  let g = fluid.grid();
+  let (vx, vy) = fluid.velocity();
+  assert!((vx[g.ix(5, 5)] - 0.05).abs() < 1e-9, "{}", vx[g.ix(5, 5)]);
+  assert!(vy[g.ix(5, 5)].abs() < 1e-9);
+
+  // a horizontal push on three rows only shears the flow
+  let mut fluid = Fluid::new(20, 10, 0.05, 0.0, 0.0).unwrap();
+  fluid.set_boundary(Boundary::Periodic);
+  fluid.set_pressure_solver(PressureSolver::Spectral);
+  let g = fluid.grid();
+  let mut fx = vec![0.0; cells];
+  for j in 3..6 {
+    for i in 0..20 {
+      fx[g.ix(i, j)] = 2.0;
+    }
+  }
+  fluid.set_force_map(fx, vec![0.0; cells]).unwrap();
+  fluid.step();
+  let band = |fluid: &Fluid| fluid.velocity_x()[g.ix(5, 4)];
+  assert!((band(&fluid) - 0.1).abs() < 1e-9, "{}", band(&fluid));
+  assert!(fluid.velocity_x()[g.ix(5, 8)].abs() < 1e-9);
+
+  fluid.clear_force_map();
+  fluid.step();
+  assert!((band(&fluid) - 0.1).abs() < 1e-9, "{}", band(&fluid));
+}
+
+/// Peak and trough of a 6x6 block of dye after drifting across a periodic
+/// domain in a uniform wind.
+fn drifted_block(advection: Advection, interpolation: Interpolation) -> (f64, f64) {
+  let mut fluid = Fluid::new(34, 34, 0.05, 0.0, 0.0).unwrap();
+  fluid.set_boundary(Boundary::Periodic);
+  fluid.set_pressure_solver(PressureSolver::Spectral);
+  fluid.set_advection(advection);
+  fluid.set_interpolation(interpolation);
+  for j in 0..34 {
+    for i in 0..34 {
+      fluid.add_velocity(i, j, 0.3, 0.1);
+    }
+  }
+  for j in 10..16 {
+    for i in 10..16 {
+      fluid.add_density(i, j, 1.0);
+    }
+  }
+  for _ in 0..40 {
+    fluid.step();
+  }
+  let density = fluid.density();
+  let max = density.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+  let min = density.iter().copied().fold(f64::INFINITY, f64::min);
+  (max, min)
+}
+
+#[test]
+fn corrected_advection_keeps_dye_sharp() {
+  let (blurred, _) = drifted_block(Advection::SemiLagrangian, Interpolation::Linear);
+  assert!(blurred < 0.6, "{}", blurred);
+  for advection in [Advection::MacCormack, Advection::Bfecc] {
+    let (max, min) = drifted_block(advection, Interpolation::Linear);
+    assert!(max > 0.9 && max <= 1.0, "{:?}: {}", advection, max);
+    assert!(min >= 0.0, "{:?}: {}", advection, min);
+  }
+}
+
+/// Mean distance of the dye from the centre of a 42x42 grid after it has been
+/// carried a full turn by a rigid rotation, starting 12 cells out.
+fn rotated_blob_radius(backtrace: Backtrace) -> f64 {
+  let (n, centre) = (42, 20.5);
+  let omega = std::f64::consts::PI;
+  let mut fluid = Fluid::new(n, n, 0.05, 0.0, 0.0).unwrap();
+  fluid.set_pressure_solver(PressureSolver::Multigrid);
+  fluid.set_backtrace(backtrace);
+  for j in 18..24 {
+    for i in 30..36 {
+      fluid.add_density(i, j, 1.0);
+    }
+  }
+
+  let g = fluid.grid();
+  let scale = (n - 2) as f64;
+  let radius = |i: i32, j: i32| (i as f64 - centre).hypot(j as f64 - centre);
+  for _ in 0..40 {
+    // hold the velocity at a rigid rotation inside a disc
+    let (vx, vy) = fluid.velocity();
+    let (vx, vy) = (vx.to_vec(), vy.to_vec());
+    for j in 1..n - 1 {
+      for i in 1..n - 1 {
+        let (dx, dy) = (i as f64 - centre, j as f64 - centre);
+        let (tx, ty) = if radius(i, j) < 18.0 {
+          (-omega * dy / scale, omega * dx / scale)
+        } else {
+          (0.0, 0.0)
+        };
+        fluid.add_velocity(i, j, tx - vx[g.ix(i, j)], ty - vy[g.ix(i, j)]);
+      }
+    }
+    fluid.step();
+  }
+
+  let density = fluid.density();
+  let (mut mass, mut moment) = (0.0, 0.0);
+  for j in 0..n {
+    for i in 0..n {
+      mass += density[g.ix(i, j)];
+      moment += density[g.ix(i, j)] * radius(i, j);
+    }
+  }
+  moment / mass
+}
+
+#[test]
+fn runge_kutta_backtrace_reduces_drift_in_rotation() {
+  let start = 12.12;
+  let euler = (rotated_blob_radius(Backtrace::Euler) - start).abs();
+  let rk2 = (rotated_blob_radius(Backtrace::Rk2) - start).abs();
+  let rk3 = (rotated_blob_radius(Backtrace::Rk3) - start).abs();
+  assert!(euler > 2.0, "{}", euler);
+  assert!(rk2 < 0.25 * euler, "{} vs {}", rk2, euler);
+  assert!(rk3 < 0.25 * euler, "{} vs {}", rk3, euler);
+}
+
+#[test]
+fn cubic_interpolation_is_sharper_without_new_extrema() {
+  let (linear, _) = drifted_block(Advection::SemiLagrangian, Interpolation::Linear);
+  for interpolation in [Interpolation::CatmullRom, Interpolation::MonotoneCubic] {
+    let (max, min) = drifted_block(Advection::SemiLagrangian, interpolation);
+    assert!(max > linear + 0.1 && max <= 1.0, "{:?}: {} vs {}", interpolation, max, linear);
+    assert!(min >= 0.0, "{:?}: {}", interpolation, min);
+  }
+}
+
+#[test]
+fn sampling_a_step_does_not_overshoot() {
+  let mut fluid = Fluid::new(12, 12, 0.1, 0.0, 0.0).unwrap();
+  for j in 0..12 {
+    for i in 6..12 {
+      fluid.add_density(i, j, 1.0);
+    }
+  }
+  let g = fluid.grid();
+  let density = fluid.density();
+  for interpolation in [Interpolation::Linear, Interpolation::CatmullRom, Interpolation::MonotoneCubic] {
+    assert_eq!(sample(g, &density, 6.0, 3.0, interpolation), 1.0);
+    assert_eq!(sample(g, &density, 3.0, 3.0, interpolation), 0.0);
+    for step in 0..=40 {
+      let x = 4.0 + step as f64 * 0.1;
+      let value = sample(g, &density, x, 5.5, interpolation);
+      assert!((0.0..=1.0).contains(&value), "{:?} at {}: {}", interpolation, x, value);
+    }
+  }
+  assert_eq!(fluid.sample_density(5.5, 2.0), 0.5);
+}
+
+#[test]
+fn staggered_grid_projects_to_zero_divergence() {
+  for periodic in [false, true] {
+    let (width, height) = (40, 32);
+    let mut fluid = MacFluid::new(width, height, 0.05, 0.0001, 0.0001).unwrap();
+    fluid.set_solver(SolverConfig::new(1000, 1e-8)).unwrap();
+    fluid.set_periodic(periodic, periodic);
+    for j in 0..height {
+      for i in 0..width {
+        let (x, y) = (i as f64 * 0.37, j as f64 * 0.23);
+        fluid.add_velocity(i, j, (x * y).sin(), (x + 2.0 * y).cos());
+      }
+    }
+    fluid.step();
+
+    let stats = fluid.solve_stats();
+    let divergence = fluid.divergence().iter().fold(0.0f64, |m, d| m.max(d.abs()));
+    assert!(stats.residual <= 1e-8, "{:?}", stats);
+    assert!(divergence < 1e-5, "periodic {}: {}", periodic, divergence);
+  }
+}
+
+#[test]
+fn staggered_grid_carries_dye_downstream() {
+  let mut fluid = MacFluid::new(32, 12, 0.05, 0.0, 0.0).unwrap();
+  fluid.set_periodic(true, false);
+  for j in 1..11 {
+    for i in 1..31 {
+      fluid.add_velocity(i, j, 0.5, 0.0);
+    }
+  }
+  fluid.add_density(8, 6, 1.0);
+  for _ in 0..10 {
+    fluid.step();
+  }
+
+  // 10 steps of 0.05 at 0.5 domain widths per second is 7.5 cells
+  let g = fluid.grid();
+  let density = fluid.density();
+  let row = (1..31).map(|i| density[g.ix(i, 6)]).collect::<Vec<_>>();
+  let peak = (1..31).max_by(|&a, &b| row[a as usize - 1].total_cmp(&row[b as usize - 1])).unwrap();
+  assert!((15..=16).contains(&peak), "{}", peak);
+  assert!(fluid.velocity_x().iter().all(|v| v.abs() < 0.5 + 1e-9));
+}
+
+#[test]
+fn cfl_limit_splits_fast_steps() {
+  let run = |cfl: f64| {
+    let mut fluid = Fluid::new(32, 12, 0.05, 0.0, 0.0).unwrap();
+    fluid.set_boundary(Boundary::Periodic);
+    fluid.set_pressure_solver(PressureSolver::Spectral);
+    fluid.set_cfl(cfl);
+    for j in 0..12 {
+      for i in 0..32 {
+        fluid.add_velocity(i, j, 2.0, 0.0);
+      }
+    }
+    fluid.add_density(8, 6, 1.0);
+    fluid.step();
+    fluid
+  };
+
+  // 2 domain widths per second for 0.05 s is 3 cells
+  let fixed = run(0.0);
+  let adaptive = run(1.0);
+  assert_eq!(fixed.substeps(), 1);
+  assert_eq!(adaptive.substeps(), 3);
+  let g = adaptive.grid();
+  let density = adaptive.density();
+  assert!(density[g.ix(11, 6)] > 0.99, "{}", density[g.ix(11, 6)]);
+}
+
+#[test]
+fn advance_runs_fixed_steps_for_elapsed_time() {
+  let mut fluid = Fluid::new(12, 12, 0.1, 0.0, 0.0).unwrap();
+  fluid.set_density_decay(1.0);
+  fluid.add_density(6, 6, 1.0);
+  let k = fluid.grid().ix(6, 6);
+
+  // 0.25 s is 2 steps with half a step left over
+  assert!((fluid.advance(0.25) - 0.5).abs() < 1e-9);
+  assert!((fluid.density()[k] - (-0.2f64).exp()).abs() < 1e-12);
+  assert!((fluid.advance(0.06) - 0.1).abs() < 1e-9);
+  assert!((fluid.density()[k] - (-0.3f64).exp()).abs() < 1e-12);
+
+  // a long stall only runs the capped number of steps
+  fluid.set_max_steps(2);
+  let alpha = fluid.advance(10.0);
+  assert!((0.0..1.0).contains(&alpha));
+  assert_eq!(fluid.steps_taken(), 2);
+  assert!((fluid.density()[k] - (-0.5f64).exp()).abs() < 1e-12);
+
+  // an infinite or NaN frame time is ignored instead of freezing the clock
+  fluid.advance(f64::INFINITY);
+  fluid.advance(f64::NAN);
+  assert_eq!(fluid.steps_taken(), 0);
+  assert!((fluid.advance(0.1) - alpha).abs() < 1e-9);
+  assert_eq!(fluid.steps_taken(), 1);
+}
+
+#[test]
+fn parameter_setters_reject_invalid_values() {
+  let mut fluid = Fluid::new(12, 12, 0.1, 0.001, 0.002).unwrap();
+  assert_eq!(fluid.set_dt(-0.1), Err(FluidError::InvalidParameter { name: "dt", value: -0.1 }));
+  assert!(fluid.set_dt(0.0).is_err());
+  assert!(fluid.set_diffusion(f64::NAN).is_err());
+  assert!(fluid.set_viscosity(f64::INFINITY).is_err());
+  assert!(fluid.set_iterations(-3.0).is_err());
+  assert!(fluid.set_iterations(2.5).is_err());
+  assert_eq!((fluid.dt(), fluid.diffusion(), fluid.viscosity(), fluid.iterations()), (0.1, 0.001, 0.002, 20));
+
+  fluid.set_dt(0.02).unwrap();
+  fluid.set_diffusion(0.0).unwrap();
+  fluid.set_viscosity(0.01).unwrap();
+  fluid.set_iterations(50.0).unwrap();
+  assert_eq!((fluid.dt(), fluid.diffusion(), fluid.viscosity(), fluid.iterations()), (0.02, 0.0, 0.01, 50));
+  assert_eq!(fluid.solver().max_iterations, 50);
+  assert!(fluid.set_solver(SolverConfig::new(0, 1e-4)).is_err());
+  assert!(fluid.set_solver(SolverConfig::new(10, f64::NAN)).is_err());
+  assert_eq!(fluid.solver(), SolverConfig::new(50, 1e-4));
+
+  // the constructors apply the same rules
+  assert!(Fluid::new(12, 12, f64::NAN, 0.0, 0.0).is_err());
+  assert!(Fluid::new(12, 12, 0.1, -1.0, 0.0).is_err());
+  assert_eq!(
+    MacFluid::new(12, 12, 0.1, 0.0, -1.0).err(),
+    Some(FluidError::InvalidParameter { name: "viscosity", value: -1.0 })
+  );
+}
+
+#[test]
+fn velocity_exports_describe_the_same_field() {
+  // the spectral solve removes exactly the central-difference divergence
+  // that `divergence` reports
+  let mut fluid = swirling_fluid(24, 20, PressureSolver::Spectral);
+  fluid.set_boundary(Boundary::Periodic);
+  let max_abs = |v: Vec<f64>| v.iter().fold(0.0f64, |m, x| m.max(x.abs()));
+  let before = max_abs(fluid.divergence());
+  fluid.step();
+
+  let (vx, vy) = (fluid.velocity_x(), fluid.velocity_y());
+  let (speed, direction) = (fluid.speed(), fluid.direction());
+  for k in 0..vx.len() {
+    assert!((speed[k] * direction[k].cos() - vx[k]).abs() < 1e-12);
+    assert!((speed[k] * direction[k].sin() - vy[k]).abs() < 1e-12);
+  }
+  let pressure = fluid.pressure();
+  assert!(max_abs(pressure.clone()) > 0.0);
+  let after = max_abs(fluid.divergence());
+  assert!(after < 1e-9 * before, "{} vs {}", after, before);
+
+  // changing dt between steps does not rescale the last solve's pressure
+  fluid.set_dt(0.1).unwrap();
+  assert_eq!(fluid.pressure(), pressure);
+}
+
+#[test]
+fn buffer_pointers_stay_put_across_steps() {
+  let mut fluid = Fluid::new(16, 16, 0.1, 0.0001, 0.0).unwrap();
+  fluid.add_scalar_field("red", 0.0).unwrap();
+  let pointers = [fluid.density_ptr(), fluid.velocity_x_ptr(), fluid.velocity_y_ptr(), fluid.scalar_ptr("red")];
+  fluid.add_density(8, 8, 1.0);
+  fluid.add_velocity(8, 8, 0.5, -0.2);
+  fluid.add_scalar("red", 4, 4, 2.0);
+  fluid.step();
+
+  assert!(fluid.scalar_ptr("blue").is_null());
+  assert_eq!(pointers, [fluid.density_ptr(), fluid.velocity_x_ptr(), fluid.velocity_y_ptr(), fluid.scalar_ptr("red")]);
+  let view = |ptr: *const f64| unsafe { std::slice::from_raw_parts(ptr, fluid.cells()) }.to_vec();
+  assert_eq!(view(pointers[0]), fluid.density());
+  assert_eq!(view(pointers[1]), fluid.velocity_x());
+  assert_eq!(view(pointers[2]), fluid.velocity_y());
+  assert_eq!(view(pointers[3]), fluid.scalar("red").unwrap());
+}
+
+#[test]
+fn grids_without_an_interior_are_rejected() {
+  for (width, height) in [(0, 0), (1, 1), (2, 10), (10, 2), (-4, 8)] {
+    let expected = Some(FluidError::GridSize { width, height });
+    assert_eq!(Fluid::new(width, height, 0.1, 0.0, 0.0).err(), expected);
+    assert_eq!(MacFluid::new(width, height, 0.1, 0.0, 0.0).err(), expected);
+  }
+  for solver in [PressureSolver::GaussSeidel, PressureSolver::Multigrid, PressureSolver::ConjugateGradient] {
+    let mut tiny = Fluid::new(3, 3, 0.1, 0.0, 0.0).unwrap();
+    tiny.set_pressure_solver(solver);
+    tiny.add_velocity(1, 1, 1.0, 0.0);
+    tiny.step();
+  }
+  MacFluid::new(3, 3, 0.1, 0.0, 0.0).unwrap().step();
+}